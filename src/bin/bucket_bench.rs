@@ -0,0 +1,51 @@
+use kiddo::Manhattan;
+use knn::{
+    kernel::epanechnikov,
+    knn::{Data, Knn, WindowType},
+    parse::breast_cancer::{parse, Diagnosis, DIMENSIONS},
+    tree::BucketSize,
+};
+use std::{error::Error, hint::black_box, time::Instant};
+
+const DATA_FILEPATH: &str = "data/breast-cancer.csv";
+const K: usize = 10;
+const ROUNDS: usize = 200;
+
+/// Times leave-one-out predictions over the whole breast cancer data for
+/// every supported kd-tree bucket size. Run with `--release`
+fn main() -> Result<(), Box<dyn Error>> {
+    let data: Vec<Data<Diagnosis, DIMENSIONS>> = parse(DATA_FILEPATH)?
+        .into_iter()
+        .map(|entry| {
+            Ok(Data {
+                features: entry.values.try_into().map_err(|_| "unexpected width")?,
+                label: entry.diagnosis,
+            })
+        })
+        .collect::<Result<_, Box<dyn Error>>>()?;
+
+    let mut knn: Knn<Manhattan, Diagnosis, DIMENSIONS> =
+        Knn::with_params(K, 0.0, &WindowType::Unfixed, epanechnikov)?;
+    knn.fit(data.clone(), None, false)?;
+
+    for bucket_size in [
+        BucketSize::B8,
+        BucketSize::B16,
+        BucketSize::B32,
+        BucketSize::B64,
+    ] {
+        knn.set_bucket_size(bucket_size);
+
+        let start = Instant::now();
+        for _ in 0..ROUNDS {
+            for (index, data_point) in data.iter().enumerate() {
+                let _ = black_box(knn.predict_excluding(&data_point.features, index));
+            }
+        }
+        let per_query = start.elapsed() / u32::try_from(ROUNDS * data.len())?;
+
+        println!("{bucket_size:?}: {per_query:?} per query");
+    }
+
+    Ok(())
+}
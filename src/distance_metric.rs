@@ -1,4 +1,50 @@
-use kiddo::{distance_metric::DistanceMetric, float::kdtree::Axis};
+use std::{cell::Cell, collections::HashMap, marker::PhantomData, sync::Arc};
+
+use kiddo::{distance_metric::DistanceMetric, float::kdtree::Axis, Manhattan, SquaredEuclidean};
+
+thread_local! {
+    static EVALUATIONS: Cell<Evaluations> = const { Cell::new(Evaluations { dist: 0, dist1: 0 }) };
+}
+
+/// Running per-thread totals of distance evaluations made through [`Counting`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Evaluations {
+    /// Full distance evaluations, one per examined point
+    pub dist: usize,
+    /// Single-axis evaluations, one per far branch the kd-tree considers
+    pub dist1: usize,
+}
+
+pub fn evaluations() -> Evaluations {
+    EVALUATIONS.with(Cell::get)
+}
+
+/// Wraps a metric and counts every evaluation into [`evaluations`]
+pub struct Counting<M> {
+    _marker: PhantomData<M>,
+}
+
+impl<A: Axis, const K: usize, M: DistanceMetric<A, K>> DistanceMetric<A, K> for Counting<M> {
+    #[inline]
+    fn dist(first: &[A; K], second: &[A; K]) -> A {
+        EVALUATIONS.with(|evaluations| {
+            let mut current = evaluations.get();
+            current.dist += 1;
+            evaluations.set(current);
+        });
+        M::dist(first, second)
+    }
+
+    #[inline]
+    fn dist1(first: A, second: A) -> A {
+        EVALUATIONS.with(|evaluations| {
+            let mut current = evaluations.get();
+            current.dist1 += 1;
+            evaluations.set(current);
+        });
+        M::dist1(first, second)
+    }
+}
 
 pub struct Chebyshev {}
 
@@ -17,3 +63,426 @@ impl<A: Axis, const K: usize> DistanceMetric<A, K> for Chebyshev {
         (first - second).abs()
     }
 }
+
+/// Reports the P-th power of the Minkowski distance, the same way
+/// `SquaredEuclidean` skips the square root, so that `dist` and `dist1`
+/// stay in the same units for kd-tree pruning
+pub struct Minkowski<const P: u32> {}
+
+impl<const P: u32> Minkowski<P> {
+    #[inline]
+    fn pow<A: Axis>(value: A) -> A {
+        (0..P).fold(A::one(), |acc, _| acc * value)
+    }
+}
+
+impl<A: Axis, const K: usize, const P: u32> DistanceMetric<A, K> for Minkowski<P> {
+    #[inline]
+    fn dist(first: &[A; K], second: &[A; K]) -> A {
+        first
+            .iter()
+            .zip(second.iter())
+            .map(|(&a_val, &b_val)| Self::pow((a_val - b_val).abs()))
+            .fold(A::zero(), std::ops::Add::add)
+    }
+
+    #[inline]
+    fn dist1(first: A, second: A) -> A {
+        Self::pow((first - second).abs())
+    }
+}
+
+/// Gower-style mixed distance: the last `C` axes are categorical and add a
+/// fixed penalty of 1 on mismatch, the rest add their absolute difference.
+///
+/// `dist1` is `min(|a - b|, 1)`, a lower bound for both kinds of axes, so
+/// kd-tree pruning stays exact
+pub struct Gower<const C: usize> {}
+
+impl<A: Axis, const K: usize, const C: usize> DistanceMetric<A, K> for Gower<C> {
+    #[inline]
+    fn dist(first: &[A; K], second: &[A; K]) -> A {
+        let categorical_start = K.saturating_sub(C);
+
+        first
+            .iter()
+            .zip(second.iter())
+            .enumerate()
+            .map(|(index, (&a_val, &b_val))| {
+                if index < categorical_start {
+                    (a_val - b_val).abs()
+                } else if a_val == b_val {
+                    A::zero()
+                } else {
+                    A::one()
+                }
+            })
+            .fold(A::zero(), std::ops::Add::add)
+    }
+
+    #[inline]
+    fn dist1(first: A, second: A) -> A {
+        (first - second).abs().min(A::one())
+    }
+}
+
+/// `1 - cos(a, b)`, treating a zero-norm vector as orthogonal to everything.
+///
+/// Cosine distance has no per-axis lower bound, so `dist1` is always zero:
+/// kd-tree queries never prune and stay exact, at the cost of visiting
+/// every point, which makes `within` and `nearest_n` effectively brute force
+pub struct Cosine {}
+
+macro_rules! impl_cosine {
+    ($float:ty) => {
+        impl<const K: usize> DistanceMetric<$float, K> for Cosine {
+            #[inline]
+            fn dist(first: &[$float; K], second: &[$float; K]) -> $float {
+                let (dot, first_norm, second_norm) = first.iter().zip(second.iter()).fold(
+                    (0.0, 0.0, 0.0),
+                    |(dot, first_norm, second_norm), (&a_val, &b_val)| {
+                        (
+                            dot + a_val * b_val,
+                            first_norm + a_val * a_val,
+                            second_norm + b_val * b_val,
+                        )
+                    },
+                );
+
+                if first_norm == 0.0 || second_norm == 0.0 {
+                    return 1.0;
+                }
+
+                1.0 - dot / (first_norm * second_norm).sqrt()
+            }
+
+            #[inline]
+            fn dist1(_first: $float, _second: $float) -> $float {
+                0.0
+            }
+        }
+    };
+}
+
+impl_cosine!(f32);
+impl_cosine!(f64);
+
+/// Turns a metric's raw values into actual distances, for metrics like
+/// `SquaredEuclidean` that skip the final root, and names the metric
+pub trait MetricKind {
+    /// Stable name reported in [`crate::knn::Provenance`]
+    fn name() -> &'static str;
+
+    #[inline]
+    #[must_use]
+    fn finalize(distance: f64) -> f64 {
+        distance
+    }
+
+    /// Inverse of [`MetricKind::finalize`], for passing a radius to the
+    /// kd-tree
+    #[inline]
+    #[must_use]
+    fn raw(distance: f64) -> f64 {
+        distance
+    }
+}
+
+impl MetricKind for SquaredEuclidean {
+    fn name() -> &'static str {
+        "squared euclidean"
+    }
+
+    #[inline]
+    fn finalize(distance: f64) -> f64 {
+        distance.sqrt()
+    }
+
+    #[inline]
+    fn raw(distance: f64) -> f64 {
+        distance.powi(2)
+    }
+}
+
+impl<const P: u32> MetricKind for Minkowski<P> {
+    fn name() -> &'static str {
+        "minkowski"
+    }
+
+    #[inline]
+    fn finalize(distance: f64) -> f64 {
+        distance.powf(1.0 / f64::from(P))
+    }
+
+    #[inline]
+    fn raw(distance: f64) -> f64 {
+        Self::pow(distance)
+    }
+}
+
+impl<M: MetricKind> MetricKind for Counting<M> {
+    fn name() -> &'static str {
+        M::name()
+    }
+
+    #[inline]
+    fn finalize(distance: f64) -> f64 {
+        M::finalize(distance)
+    }
+
+    #[inline]
+    fn raw(distance: f64) -> f64 {
+        M::raw(distance)
+    }
+}
+
+impl MetricKind for Manhattan {
+    fn name() -> &'static str {
+        "manhattan"
+    }
+}
+
+impl MetricKind for Chebyshev {
+    fn name() -> &'static str {
+        "chebyshev"
+    }
+}
+
+impl<const C: usize> MetricKind for Gower<C> {
+    fn name() -> &'static str {
+        "gower"
+    }
+}
+
+impl MetricKind for Cosine {
+    fn name() -> &'static str {
+        "cosine"
+    }
+}
+
+/// Distance between two points in final units, the dimension is only known
+/// at runtime
+pub type DistanceFn = Arc<dyn Fn(&[f64], &[f64]) -> f64 + Send + Sync>;
+
+/// Metrics looked up by name, used through brute force so a new metric
+/// needs no kd-tree `DistanceMetric` implementation
+#[derive(Clone, Default)]
+pub struct MetricRegistry {
+    metrics: HashMap<String, DistanceFn>,
+}
+
+impl MetricRegistry {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `manhattan`, `euclidean` and `chebyshev`
+    #[must_use]
+    pub fn with_builtins() -> Self {
+        let mut registry = Self::new();
+
+        registry.register("manhattan", |first, second| {
+            first
+                .iter()
+                .zip(second)
+                .map(|(a_val, b_val)| (a_val - b_val).abs())
+                .sum()
+        });
+        registry.register("euclidean", |first, second| {
+            first
+                .iter()
+                .zip(second)
+                .map(|(a_val, b_val)| (a_val - b_val).powi(2))
+                .sum::<f64>()
+                .sqrt()
+        });
+        registry.register("chebyshev", |first, second| {
+            first
+                .iter()
+                .zip(second)
+                .map(|(a_val, b_val)| (a_val - b_val).abs())
+                .fold(0.0, f64::max)
+        });
+
+        registry
+    }
+
+    /// Replaces any metric already registered under `name`
+    pub fn register(
+        &mut self,
+        name: &str,
+        metric: impl Fn(&[f64], &[f64]) -> f64 + Send + Sync + 'static,
+    ) {
+        self.metrics.insert(name.to_string(), Arc::new(metric));
+    }
+
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<DistanceFn> {
+        self.metrics.get(name).cloned()
+    }
+
+    /// Registered names in sorted order
+    #[must_use]
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.metrics.keys().map(String::as_str).collect();
+        names.sort_unstable();
+
+        names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POINTS: [[f64; 3]; 4] = [
+        [0.0, 0.0, 0.0],
+        [1.0, -2.0, 0.5],
+        [3.5, 1.0, -4.0],
+        [-0.25, 6.0, 2.0],
+    ];
+
+    #[test]
+    fn metrics_have_stable_names() {
+        assert_eq!(Manhattan::name(), "manhattan");
+        assert_eq!(SquaredEuclidean::name(), "squared euclidean");
+        assert_eq!(Chebyshev::name(), "chebyshev");
+        assert_eq!(Minkowski::<3>::name(), "minkowski");
+        assert_eq!(Cosine::name(), "cosine");
+        assert_eq!(Gower::<1>::name(), "gower");
+        assert_eq!(Counting::<Chebyshev>::name(), "chebyshev");
+    }
+
+    #[test]
+    fn registered_builtins_match_the_kd_tree_metrics() {
+        let registry = MetricRegistry::with_builtins();
+        assert_eq!(registry.names(), ["chebyshev", "euclidean", "manhattan"]);
+
+        for first in &POINTS {
+            for second in &POINTS {
+                let distance = |name: &str| registry.get(name).unwrap()(first, second);
+
+                assert!((distance("manhattan") - Manhattan::dist(first, second)).abs() < 1e-12);
+                assert!(
+                    (distance("euclidean")
+                        - SquaredEuclidean::finalize(SquaredEuclidean::dist(first, second)))
+                    .abs()
+                        < 1e-12
+                );
+                assert!((distance("chebyshev") - Chebyshev::dist(first, second)).abs() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn registering_a_name_again_replaces_the_metric() {
+        let mut registry = MetricRegistry::new();
+        assert!(registry.get("first axis").is_none());
+
+        registry.register("first axis", |first, second| (first[0] - second[0]).abs());
+        registry.register("first axis", |first, second| (first[0] - second[0]).powi(2));
+
+        assert_eq!(registry.names(), ["first axis"]);
+        assert!((registry.get("first axis").unwrap()(&[0.0], &[3.0]) - 9.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn minkowski_matches_manhattan_and_euclidean() {
+        for first in &POINTS {
+            for second in &POINTS {
+                let manhattan: f64 = Manhattan::dist(first, second);
+                let squared_euclidean: f64 = SquaredEuclidean::dist(first, second);
+
+                assert!((Minkowski::<1>::dist(first, second) - manhattan).abs() < 1e-12);
+                assert!((Minkowski::<2>::dist(first, second) - squared_euclidean).abs() < 1e-12);
+                assert!(
+                    (Minkowski::<2>::finalize(Minkowski::<2>::dist(first, second))
+                        - SquaredEuclidean::finalize(squared_euclidean))
+                    .abs()
+                        < 1e-12
+                );
+
+                for (&a_val, &b_val) in first.iter().zip(second) {
+                    let manhattan: f64 = <Manhattan as DistanceMetric<f64, 3>>::dist1(a_val, b_val);
+                    let squared_euclidean: f64 =
+                        <SquaredEuclidean as DistanceMetric<f64, 3>>::dist1(a_val, b_val);
+
+                    assert!(
+                        (<Minkowski<1> as DistanceMetric<f64, 3>>::dist1(a_val, b_val) - manhattan)
+                            .abs()
+                            < 1e-12
+                    );
+                    assert!(
+                        (<Minkowski<2> as DistanceMetric<f64, 3>>::dist1(a_val, b_val)
+                            - squared_euclidean)
+                            .abs()
+                            < 1e-12
+                    );
+                }
+            }
+        }
+    }
+
+    fn cosine(first: [f64; 2], second: [f64; 2]) -> f64 {
+        Cosine::dist(&first, &second)
+    }
+
+    #[test]
+    fn cosine_distance_edge_cases() {
+        assert!(cosine([1.0, 2.0], [2.0, 4.0]).abs() < 1e-12);
+        assert!((cosine([1.0, 0.0], [0.0, 3.0]) - 1.0).abs() < 1e-12);
+        assert!((cosine([1.0, -2.0], [-1.0, 2.0]) - 2.0).abs() < 1e-12);
+        // a zero-norm vector counts as orthogonal, even to itself
+        assert!((cosine([0.0, 0.0], [1.0, 1.0]) - 1.0).abs() < 1e-12);
+        assert!((cosine([0.0, 0.0], [0.0, 0.0]) - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn cosine_kd_tree_queries_match_brute_force() {
+        let points: Vec<[f64; 2]> = (0..40)
+            .map(|index| {
+                let angle = f64::from(index) * 0.37;
+                let length = 1.0 + f64::from(index % 7);
+                [length * angle.cos(), length * angle.sin()]
+            })
+            .collect();
+        let mut tree: kiddo::KdTree<f64, 2> = kiddo::KdTree::new();
+        for (index, point) in (0_u64..).zip(&points) {
+            tree.add(point, index);
+        }
+
+        for query in [[1.0, 0.2], [-3.0, 1.0], [0.5, -2.0]] {
+            let mut expected: Vec<(f64, u64)> = (0_u64..)
+                .zip(&points)
+                .map(|(index, point)| (Cosine::dist(&query, point), index))
+                .collect();
+            expected.sort_by(|first, second| first.0.total_cmp(&second.0));
+
+            let nearest = tree.nearest_n::<Cosine>(&query, 5);
+            for (found, (distance, _)) in nearest.iter().zip(&expected) {
+                assert!((found.distance - distance).abs() < 1e-12);
+            }
+
+            let within = tree.within::<Cosine>(&query, 0.1);
+            let expected_within = expected
+                .iter()
+                .filter(|(distance, _)| *distance <= 0.1)
+                .count();
+            assert_eq!(within.len(), expected_within);
+        }
+    }
+
+    #[test]
+    fn gower_adds_a_fixed_penalty_on_categorical_mismatch() {
+        let male: [f64; 3] = [0.5, -1.0, 1.0];
+        let female: [f64; 3] = [0.5, -1.0, 0.0];
+        let far_female: [f64; 3] = [2.5, 1.0, 0.0];
+
+        assert!((Gower::<1>::dist(&male, &female) - 1.0).abs() < 1e-12);
+        // the categorical axis adds 1, not the difference of its codes
+        assert!((Gower::<1>::dist(&male, &[0.5, -1.0, 7.0]) - 1.0).abs() < 1e-12);
+        assert!((Gower::<1>::dist(&female, &far_female) - 4.0).abs() < 1e-12);
+        assert!((Gower::<1>::dist(&male, &far_female) - 5.0).abs() < 1e-12);
+    }
+}
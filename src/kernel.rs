@@ -1,3 +1,154 @@
+use std::sync::Arc;
+
+/// Weight of a neighbour at a normalized distance. Plain `fn(f64) -> f64`
+/// kernels implement it through the blanket impl and are named "custom",
+/// the unit structs below are the named built-in kernels and other structs
+/// can carry parameters
+pub trait Kernel: Send + Sync {
+    fn eval(&self, distance: f64) -> f64;
+
+    fn name(&self) -> &'static str {
+        "custom"
+    }
+}
+
+impl<F: Fn(f64) -> f64 + Send + Sync + 'static> Kernel for F {
+    fn eval(&self, distance: f64) -> f64 {
+        self(distance)
+    }
+}
+
+/// Shared kernels keep the name of the kernel they point to
+impl<K: Kernel + ?Sized> Kernel for Arc<K> {
+    fn eval(&self, distance: f64) -> f64 {
+        (**self).eval(distance)
+    }
+
+    fn name(&self) -> &'static str {
+        (**self).name()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Uniform;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Triangular;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Epanechnikov;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Quartic;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Triweight;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Tricube;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Gaussian;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TruncatedGaussian;
+
+/// Gaussian with standard deviation `sigma` in normalized distance units
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GaussianWithSigma {
+    pub sigma: f64,
+}
+
+impl Kernel for Uniform {
+    fn eval(&self, distance: f64) -> f64 {
+        uniform(distance)
+    }
+
+    fn name(&self) -> &'static str {
+        "uniform"
+    }
+}
+
+impl Kernel for Triangular {
+    fn eval(&self, distance: f64) -> f64 {
+        triangular(distance)
+    }
+
+    fn name(&self) -> &'static str {
+        "triangular"
+    }
+}
+
+impl Kernel for Epanechnikov {
+    fn eval(&self, distance: f64) -> f64 {
+        epanechnikov(distance)
+    }
+
+    fn name(&self) -> &'static str {
+        "epanechnikov"
+    }
+}
+
+impl Kernel for Quartic {
+    fn eval(&self, distance: f64) -> f64 {
+        quartic(distance)
+    }
+
+    fn name(&self) -> &'static str {
+        "quartic"
+    }
+}
+
+impl Kernel for Triweight {
+    fn eval(&self, distance: f64) -> f64 {
+        triweight(distance)
+    }
+
+    fn name(&self) -> &'static str {
+        "triweight"
+    }
+}
+
+impl Kernel for Tricube {
+    fn eval(&self, distance: f64) -> f64 {
+        tricube(distance)
+    }
+
+    fn name(&self) -> &'static str {
+        "tricube"
+    }
+}
+
+impl Kernel for Gaussian {
+    fn eval(&self, distance: f64) -> f64 {
+        gaussian(distance)
+    }
+
+    fn name(&self) -> &'static str {
+        "gaussian"
+    }
+}
+
+impl Kernel for TruncatedGaussian {
+    fn eval(&self, distance: f64) -> f64 {
+        truncated_gaussian(distance)
+    }
+
+    fn name(&self) -> &'static str {
+        "truncated gaussian"
+    }
+}
+
+impl Kernel for GaussianWithSigma {
+    fn eval(&self, distance: f64) -> f64 {
+        gaussian(distance / self.sigma) / self.sigma
+    }
+
+    fn name(&self) -> &'static str {
+        "gaussian with sigma"
+    }
+}
+
 pub fn uniform(distance: f64) -> f64 {
     if distance < 1.0 {
         0.5
@@ -22,6 +173,169 @@ pub fn epanechnikov(distance: f64) -> f64 {
     }
 }
 
+pub fn quartic(distance: f64) -> f64 {
+    if distance.abs() < 1.0 {
+        (1.0 - distance.powi(2)).powi(2) * 15.0 / 16.0
+    } else {
+        0.0
+    }
+}
+
+pub fn triweight(distance: f64) -> f64 {
+    if distance.abs() < 1.0 {
+        (1.0 - distance.powi(2)).powi(3) * 35.0 / 32.0
+    } else {
+        0.0
+    }
+}
+
+pub fn tricube(distance: f64) -> f64 {
+    if distance.abs() < 1.0 {
+        (1.0 - distance.abs().powi(3)).powi(3)
+    } else {
+        0.0
+    }
+}
+
+/// Never zero, so under a `Fixed` window every point in the radius still
+/// gets a weight that barely depends on the radius. Use
+/// [`truncated_gaussian`] when the radius should cut off
 pub fn gaussian(distance: f64) -> f64 {
     (1.0 / (2.0 * std::f64::consts::PI).sqrt()) * (-distance.powi(2) / 2.0).exp()
 }
+
+/// [`gaussian`] cut off at a normalized distance of 1 like the compact
+/// kernels
+pub fn truncated_gaussian(distance: f64) -> f64 {
+    if distance.abs() < 1.0 {
+        gaussian(distance)
+    } else {
+        0.0
+    }
+}
+
+/// The built-in kernel whose [`Kernel::name`] is `name`, `None` for custom
+/// kernels
+pub fn by_name(name: &str) -> Option<Arc<dyn Kernel>> {
+    let kernel: Arc<dyn Kernel> = match name {
+        "uniform" => Arc::new(Uniform),
+        "triangular" => Arc::new(Triangular),
+        "epanechnikov" => Arc::new(Epanechnikov),
+        "quartic" => Arc::new(Quartic),
+        "triweight" => Arc::new(Triweight),
+        "tricube" => Arc::new(Tricube),
+        "gaussian" => Arc::new(Gaussian),
+        "truncated gaussian" => Arc::new(TruncatedGaussian),
+        _ => return None,
+    };
+
+    Some(kernel)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn built_in_kernels_round_trip_through_their_names() {
+        let kernels: [&dyn Kernel; 8] = [
+            &Uniform,
+            &Triangular,
+            &Epanechnikov,
+            &Quartic,
+            &Triweight,
+            &Tricube,
+            &Gaussian,
+            &TruncatedGaussian,
+        ];
+
+        for kernel in kernels {
+            let named = by_name(kernel.name()).expect("built-in kernels have a name");
+            assert_eq!(named.name(), kernel.name());
+            for distance in [0.0, 0.5, 0.99, 1.0, 1.5] {
+                assert_eq!(
+                    named.eval(distance).to_bits(),
+                    kernel.eval(distance).to_bits()
+                );
+            }
+        }
+    }
+
+    /// Every kernel but `gaussian` gives no weight from a normalized distance
+    /// of 1 on, so a fixed window cuts off at its radius
+    #[test]
+    fn kernels_cut_off_at_the_window() {
+        let kernels: [&dyn Kernel; 8] = [
+            &Uniform,
+            &Triangular,
+            &Epanechnikov,
+            &Quartic,
+            &Triweight,
+            &Tricube,
+            &Gaussian,
+            &TruncatedGaussian,
+        ];
+
+        for kernel in kernels {
+            for step in 0..=40 {
+                let distance = f64::from(step) * 0.05;
+                let weight = kernel.eval(distance);
+
+                assert!(
+                    weight.is_finite() && weight >= 0.0,
+                    "{} gives weight {weight} at {distance}",
+                    kernel.name()
+                );
+                assert!(
+                    kernel.name() == "gaussian" || distance < 1.0 || weight == 0.0,
+                    "{} gives weight {weight} outside the window at {distance}",
+                    kernel.name()
+                );
+            }
+        }
+
+        assert!(gaussian(1.5) > 0.0);
+        assert!((truncated_gaussian(0.5) - gaussian(0.5)).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn quartic_and_triweight_peak_at_their_constants_and_clip_at_one() {
+        assert!((quartic(0.0) - 15.0 / 16.0).abs() < 1e-12);
+        assert!((triweight(0.0) - 35.0 / 32.0).abs() < 1e-12);
+        assert!((quartic(0.5) - 0.75_f64.powi(2) * 15.0 / 16.0).abs() < 1e-12);
+        assert!((triweight(-0.5) - 0.75_f64.powi(3) * 35.0 / 32.0).abs() < 1e-12);
+
+        for distance in [1.0, -1.0, 1.5] {
+            assert!(quartic(distance).abs() < f64::EPSILON);
+            assert!(triweight(distance).abs() < f64::EPSILON);
+        }
+        // triweight is the sharper of the two
+        assert!(triweight(0.9) < quartic(0.9));
+    }
+
+    #[test]
+    fn functions_are_custom_kernels() {
+        let pointer: fn(f64) -> f64 = epanechnikov;
+
+        assert_eq!(Kernel::name(&epanechnikov), "custom");
+        assert_eq!(Kernel::name(&pointer), "custom");
+        assert!(by_name("custom").is_none());
+    }
+
+    #[test]
+    fn shared_kernels_keep_their_names() {
+        let shared: Arc<dyn Kernel> = Arc::new(Quartic);
+        let model_kernel: Arc<dyn Kernel> = Arc::new(Arc::clone(&shared));
+
+        assert_eq!(model_kernel.name(), "quartic");
+        assert_eq!(model_kernel.eval(0.5).to_bits(), quartic(0.5).to_bits());
+    }
+
+    #[test]
+    fn gaussian_with_sigma_widens_the_gaussian() {
+        let wide = GaussianWithSigma { sigma: 2.0 };
+
+        assert!((wide.eval(2.0) - gaussian(1.0) / 2.0).abs() < f64::EPSILON);
+        assert!(wide.eval(2.0) / wide.eval(0.0) > gaussian(2.0) / gaussian(0.0));
+    }
+}
@@ -1,136 +1,3615 @@
-use std::{collections::HashMap, error::Error, marker::PhantomData};
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fmt,
+    hash::Hash,
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
 
-use kiddo::{distance_metric::DistanceMetric, float::kdtree::KdTree};
+use kiddo::{distance_metric::DistanceMetric, float::kdtree::Axis, NearestNeighbour};
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+#[cfg(feature = "serde")]
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+#[cfg(feature = "serde")]
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+};
 
-use crate::parse::breast_cancer::Diagnosis;
+use crate::{
+    distance_metric::{evaluations, Counting, DistanceFn, MetricKind, MetricRegistry},
+    kernel::{self, Kernel},
+    metrics::{f1_macro, quantile, ratio},
+    parse::breast_cancer::Diagnosis,
+    tree::{max_shared, BucketSize, Tree},
+    whitening::Whitening,
+};
 
-pub const DIMENSIONS: usize = 30;
+/// Feature storage type. `f32` halves the memory of the points and the tree,
+/// distances and weights are still computed in `f64`
+pub trait Scalar: Axis + Into<f64> {
+    fn from_f64(value: f64) -> Self;
+}
+
+impl Scalar for f32 {
+    #[allow(clippy::cast_possible_truncation)]
+    fn from_f64(value: f64) -> Self {
+        value as Self
+    }
+}
 
-const BUCKET_SIZE: usize = 32;
+impl Scalar for f64 {
+    fn from_f64(value: f64) -> Self {
+        value
+    }
+}
 
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum WindowType {
     Fixed,
     Unfixed,
+    /// At most `max_k` nearest neighbours, all within `radius`. Distances are
+    /// normalized by the radius as in `Fixed`
+    Capped {
+        radius: f64,
+        max_k: usize,
+    },
+    /// The `m` nearest neighbours, with distances normalized by the distance
+    /// to the next nearest point, a per-query bandwidth that widens in sparse
+    /// regions. Compact kernels give all `m` non-zero weight unless they tie
+    /// with that point
+    Adaptive {
+        m: usize,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Backend {
+    #[default]
+    KdTree,
+    BruteForce,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct QueryStats {
+    pub queries: usize,
+    /// Far branches considered by the kd-tree, always zero for brute force
+    pub nodes_visited: usize,
+    pub points_examined: usize,
+}
+
+impl QueryStats {
+    #[must_use]
+    pub fn mean_points_examined(&self) -> f64 {
+        if self.queries == 0 {
+            0.0
+        } else {
+            self.points_examined as f64 / self.queries as f64
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct QueryStatsCounter {
+    queries: AtomicUsize,
+    nodes_visited: AtomicUsize,
+    points_examined: AtomicUsize,
+}
+
+impl QueryStatsCounter {
+    fn get(&self) -> QueryStats {
+        QueryStats {
+            queries: self.queries.load(Ordering::Relaxed),
+            nodes_visited: self.nodes_visited.load(Ordering::Relaxed),
+            points_examined: self.points_examined.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Clone for QueryStatsCounter {
+    fn clone(&self) -> Self {
+        let stats = self.get();
+
+        Self {
+            queries: AtomicUsize::new(stats.queries),
+            nodes_visited: AtomicUsize::new(stats.nodes_visited),
+            points_examined: AtomicUsize::new(stats.points_examined),
+        }
+    }
+}
+
+/// How much each neighbour's vote counts
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum VoteWeighting {
+    /// The model's kernel applied to the normalized distance
+    #[default]
+    Kernel,
+    /// `1 / (distance + epsilon)` of the raw distance, `epsilon` keeps exact
+    /// matches finite
+    InverseDistance { epsilon: f64 },
+    /// Every neighbour votes 1.0 regardless of distance, a plain majority
+    /// vote when fitted without sample weights. Baseline for how much the
+    /// kernel weighting helps
+    Uniform,
+}
+
+/// Which class wins when the best class scores are equal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum TieBreak<L> {
+    /// The tied class of the nearest neighbour, or the smallest tied class
+    /// when the neighbours are unknown
+    #[default]
+    PreferNearest,
+    /// The given class if it is among the tied ones, otherwise the smallest
+    /// tied class
+    PreferLabel(L),
+    /// Fail the prediction with [`KnnError::Tie`]
+    Error,
+}
+
+/// Scores closer than this to the best one count as tied with it
+const TIE_EPSILON: f64 = 1e-12;
+
+/// Distance the `Unfixed` window divides neighbour distances by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Bandwidth {
+    #[default]
+    MaxNeighbor,
+    MedianNeighbor,
+}
+
+/// How kernel outputs are checked. Kernels must return finite, non-negative
+/// values: `Lenient` replaces anything else with zero and counts it in
+/// [`Knn::warnings`], `Strict` fails the prediction instead
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum KernelCheck {
+    Off,
+    Lenient,
+    Strict,
+}
+
+impl Default for KernelCheck {
+    fn default() -> Self {
+        if cfg!(debug_assertions) {
+            Self::Lenient
+        } else {
+            Self::Off
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct WarningCounter(AtomicUsize);
+
+impl Clone for WarningCounter {
+    fn clone(&self) -> Self {
+        Self(AtomicUsize::new(self.0.load(Ordering::Relaxed)))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Provenance {
+    pub neighbors: Vec<usize>,
+    pub kernel_weights: Vec<f64>,
+    pub weights: Vec<f64>,
+    pub kernel: &'static str,
+    pub metric: &'static str,
+    pub window: WindowType,
+}
+
+pub trait Label: Copy + Eq + Hash + Ord {}
+
+impl<T: Copy + Eq + Hash + Ord> Label for T {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum KnnError {
+    NoNeighbors,
+    NotFitted,
+    InvalidInput(String),
+    WeightLengthMismatch {
+        expected: usize,
+        got: usize,
+    },
+    InvalidWeight {
+        index: usize,
+        weight: f64,
+    },
+    UnknownPoint(usize),
+    Tie,
+    /// The window's radius is too small to reach any training point
+    NoNeighborsInRadius {
+        radius: f64,
+    },
+}
+
+impl fmt::Display for KnnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoNeighbors => write!(f, "no neighbors found for prediction"),
+            Self::NotFitted => write!(f, "model has no training data"),
+            Self::InvalidInput(message) => write!(f, "invalid input: {message}"),
+            Self::WeightLengthMismatch { expected, got } => {
+                write!(f, "expected {expected} weights, got {got}")
+            }
+            Self::InvalidWeight { index, weight } => {
+                write!(
+                    f,
+                    "weight {index} is {weight}, expected a finite non-negative value"
+                )
+            }
+            Self::UnknownPoint(index) => write!(f, "no training point with index {index}"),
+            Self::Tie => write!(f, "several classes have the best score"),
+            Self::NoNeighborsInRadius { radius } => {
+                write!(f, "no training points within radius {radius}")
+            }
+        }
+    }
+}
+
+impl Error for KnnError {}
+
+impl KnnError {
+    /// Whether the query's window held no training points, as opposed to the
+    /// model or the input being unusable
+    #[must_use]
+    pub fn is_empty_window(&self) -> bool {
+        matches!(self, Self::NoNeighbors | Self::NoNeighborsInRadius { .. })
+    }
+}
+
+/// Outcome of [`Knn::predict_fixed`] other than a class
+#[derive(Debug, Clone, PartialEq)]
+pub enum WindowError {
+    /// No training point lies within the fixed window's radius
+    Empty,
+    /// Any other failure, e.g. an unfitted model or a non-finite query
+    Prediction(KnnError),
+}
+
+impl fmt::Display for WindowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "no training points within the window"),
+            Self::Prediction(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl Error for WindowError {}
+
+/// `InvalidInput` naming the first NaN or infinite feature, `point` is the
+/// training point index or `None` for a query
+fn check_finite<const D: usize, T: Scalar>(
+    features: &[T; D],
+    point: Option<usize>,
+) -> Result<(), KnnError> {
+    match (features.iter().position(|&value| !value.is_finite()), point) {
+        (None, _) => Ok(()),
+        (Some(axis), Some(point)) => Err(KnnError::InvalidInput(format!(
+            "feature {axis} of point {point} is {}",
+            features[axis].into()
+        ))),
+        (Some(axis), None) => Err(KnnError::InvalidInput(format!(
+            "feature {axis} of the query is {}",
+            features[axis].into()
+        ))),
+    }
+}
+
+/// `kernel` of every distance divided by the window's bandwidth, the
+/// weights [`VoteWeighting::Kernel`] votes with for `distances` sorted
+/// nearest first. For an `Adaptive { m }` window `distances` may hold the
+/// `m + 1`-th neighbour, which sets the bandwidth and gets no weight
+pub fn kernel_weights(
+    distances: &[f64],
+    window: WindowType,
+    radius: f64,
+    bandwidth: Bandwidth,
+    kernel: &dyn Kernel,
+) -> Vec<f64> {
+    normalized_distances(distances, window, radius, bandwidth)
+        .into_iter()
+        .map(|distance| kernel.eval(distance))
+        .collect()
+}
+
+/// `distances` divided by the window's bandwidth: the radius for `Fixed`
+/// and `Capped`, the `bandwidth` statistic of the neighbours for `Unfixed`
+/// and the bandwidth point for `Adaptive`, which is left out
+fn normalized_distances(
+    distances: &[f64],
+    window: WindowType,
+    radius: f64,
+    bandwidth: Bandwidth,
+) -> Vec<f64> {
+    let (distances, adaptive) = match window {
+        WindowType::Adaptive { m } if distances.len() > m => (&distances[..m], Some(distances[m])),
+        _ => (distances, None),
+    };
+    let Some(&farthest) = distances.last() else {
+        return Vec::new();
+    };
+    let normalizer = window_bandwidth(
+        window,
+        radius,
+        bandwidth,
+        distances,
+        adaptive.unwrap_or(farthest),
+    );
+
+    distances
+        .iter()
+        .map(|&distance| normalize(distance, normalizer))
+        .collect()
+}
+
+/// What the window divides distances by, `adaptive` is the `Adaptive`
+/// window's bandwidth point
+fn window_bandwidth(
+    window: WindowType,
+    radius: f64,
+    bandwidth: Bandwidth,
+    distances: &[f64],
+    adaptive: f64,
+) -> f64 {
+    let farthest = distances.last().copied().unwrap_or(0.0);
+
+    match window {
+        WindowType::Fixed => radius,
+        WindowType::Capped { radius, .. } => radius,
+        WindowType::Adaptive { .. } => adaptive,
+        WindowType::Unfixed => match bandwidth {
+            Bandwidth::MaxNeighbor => farthest,
+            // a zero median would put every farther neighbour at
+            // infinity, only an all-zero neighbourhood has no width
+            Bandwidth::MedianNeighbor => match quantile(distances, 0.5) {
+                median if median > 0.0 => median,
+                _ => farthest,
+            },
+        },
+    }
+}
+
+/// 0.0 when every neighbour coincides with the query, dividing would give NaN
+fn normalize(distance: f64, normalizer: f64) -> f64 {
+    if normalizer == 0.0 {
+        0.0
+    } else {
+        distance / normalizer
+    }
+}
+
+/// `InvalidInput` for an inverse-distance epsilon that can't keep exact
+/// matches finite
+fn check_voting(voting: VoteWeighting) -> Result<(), KnnError> {
+    match voting {
+        VoteWeighting::InverseDistance { epsilon } if !(epsilon.is_finite() && epsilon > 0.0) => {
+            Err(KnnError::InvalidInput(format!(
+                "inverse distance voting needs a positive finite epsilon, got {epsilon}"
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// `bucket_size` or the smallest larger one a kd-tree leaf with `shared`
+/// points on one value of an axis can be split in, kiddo panics otherwise
+fn fitting_bucket(bucket_size: BucketSize, shared: usize) -> Result<BucketSize, KnnError> {
+    bucket_size.fitting(shared).ok_or_else(|| {
+        KnnError::InvalidInput(format!(
+            "{shared} points share a value on one axis, a kd-tree bucket holds at most {}",
+            BucketSize::B64.capacity() - 1
+        ))
+    })
 }
 
-#[derive(Clone, Copy)]
-pub struct Data {
-    pub features: [f64; DIMENSIONS],
-    pub label: Diagnosis,
+/// `InvalidInput` when `window` lacks the parameter it relies on, see
+/// [`KnnBuilder::build`]
+fn check_window(window: WindowType, k: usize, radius: f64) -> Result<(), KnnError> {
+    match window {
+        WindowType::Fixed if !(radius.is_finite() && radius > 0.0) => Err(KnnError::InvalidInput(
+            format!("fixed window needs a positive radius, got {radius}"),
+        )),
+        WindowType::Unfixed if k == 0 => Err(KnnError::InvalidInput(
+            "unfixed window needs a positive k".to_string(),
+        )),
+        WindowType::Capped { radius, max_k }
+            if !(radius.is_finite() && radius > 0.0) || max_k == 0 =>
+        {
+            Err(KnnError::InvalidInput(format!(
+                "capped window needs a positive radius and max_k, got {radius} and {max_k}"
+            )))
+        }
+        WindowType::Adaptive { m: 0 } => Err(KnnError::InvalidInput(
+            "adaptive window needs a positive m".to_string(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn is_valid_weight(weight: f64) -> bool {
+    weight.is_finite() && weight >= 0.0
+}
+
+/// A training point in a query's window and how much its vote counts
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Neighbor<L: Label> {
+    pub index: usize,
+    /// Distance to the query in the metric's units
+    pub raw_distance: f64,
+    /// `raw_distance` divided by the window's bandwidth, 0.0 when the
+    /// bandwidth is zero
+    pub normalized_distance: f64,
+    /// The vote before the sample weight, see [`VoteWeighting`]
+    pub kernel_weight: f64,
+    pub sample_weight: f64,
+    pub label: L,
 }
 
 #[derive(Clone)]
-pub struct Knn<M: DistanceMetric<f64, DIMENSIONS>> {
+#[must_use]
+pub struct KnnBuilder {
+    k: usize,
+    radius: f64,
+    window: WindowType,
+    kernel: Arc<dyn Kernel>,
+    voting: VoteWeighting,
+    bucket_size: BucketSize,
+    capacity: usize,
+}
+
+impl KnnBuilder {
+    pub fn new() -> Self {
+        Self {
+            k: 1,
+            radius: 0.0,
+            window: WindowType::Unfixed,
+            kernel: Arc::new(kernel::Uniform),
+            voting: VoteWeighting::default(),
+            bucket_size: BucketSize::default(),
+            capacity: 0,
+        }
+    }
+
+    /// A `k` above the number of fitted points is clamped to it, so every
+    /// point is a neighbour
+    pub fn k(mut self, k: usize) -> Self {
+        self.k = k;
+        self
+    }
+
+    pub fn radius(mut self, radius: f64) -> Self {
+        self.radius = radius;
+        self
+    }
+
+    pub fn window(mut self, window: WindowType) -> Self {
+        self.window = window;
+        self
+    }
+
+    pub fn kernel(mut self, kernel: impl Kernel + 'static) -> Self {
+        self.kernel = Arc::new(kernel);
+        self
+    }
+
+    pub fn voting(mut self, voting: VoteWeighting) -> Self {
+        self.voting = voting;
+        self
+    }
+
+    pub fn bucket_size(mut self, bucket_size: BucketSize) -> Self {
+        self.bucket_size = bucket_size;
+        self
+    }
+
+    /// Only sizes the tree for points added before the first `fit`, which
+    /// sizes it from its data
+    #[deprecated(note = "the tree is sized from the data passed to `fit`")]
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Fails when the window lacks the parameter it relies on: a positive
+    /// finite radius for `Fixed`, a positive k for `Unfixed`, both for
+    /// `Capped`, a positive m for `Adaptive`. Inverse-distance voting needs a
+    /// positive finite epsilon
+    pub fn build<M, L: Label, const D: usize, T: Scalar>(self) -> Result<Knn<M, L, D, T>, KnnError>
+    where
+        M: DistanceMetric<T, D> + MetricKind,
+    {
+        check_voting(self.voting)?;
+        check_window(self.window, self.k, self.radius)?;
+
+        Ok(self.assemble())
+    }
+
+    fn assemble<M, L: Label, const D: usize, T: Scalar>(self) -> Knn<M, L, D, T>
+    where
+        M: DistanceMetric<T, D> + MetricKind,
+    {
+        Knn {
+            k: self.k,
+            radius: self.radius,
+            kernel: self.kernel,
+            voting: self.voting,
+            window: self.window,
+            bandwidth: Bandwidth::default(),
+            kd_tree: Tree::with_capacity(self.bucket_size, self.capacity),
+            data: Vec::new(),
+            weights: Vec::new(),
+            removed: Vec::new(),
+            live_points: 0,
+            classes: Vec::new(),
+            class_bias: BTreeMap::new(),
+            tie_break: TieBreak::default(),
+            smoothing: 0.0,
+            kernel_check: KernelCheck::default(),
+            warnings: WarningCounter::default(),
+            backend: Backend::default(),
+            registered_metric: None,
+            instrumented: false,
+            canonical_order: false,
+            query_stats: QueryStatsCounter::default(),
+            whitening: None,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl Default for KnnBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Data<L: Label, const D: usize, T: Scalar = f64> {
+    pub features: [T; D],
+    pub label: L,
+}
+
+pub struct Knn<M, L: Label, const D: usize, T: Scalar = f64>
+where
+    M: DistanceMetric<T, D> + MetricKind,
+{
     k: usize,
     radius: f64,
-    kernel: fn(f64) -> f64,
+    kernel: Arc<dyn Kernel>,
+    voting: VoteWeighting,
     window: WindowType,
-    kd_tree: KdTree<f64, usize, DIMENSIONS, BUCKET_SIZE, u32>,
-    data: Vec<Data>,
+    bandwidth: Bandwidth,
+    kd_tree: Tree<T, D>,
+    data: Vec<Data<L, D, T>>,
     weights: Vec<f64>,
+    removed: Vec<bool>,
+    /// Points not in `removed`, kept up to date so `len` is cheap on every
+    /// query
+    live_points: usize,
+    classes: Vec<L>,
+    class_bias: BTreeMap<L, f64>,
+    tie_break: TieBreak<L>,
+    smoothing: f64,
+    kernel_check: KernelCheck,
+    warnings: WarningCounter,
+    backend: Backend,
+    instrumented: bool,
+    canonical_order: bool,
+    query_stats: QueryStatsCounter,
+    whitening: Option<Whitening<D>>,
+    registered_metric: Option<DistanceFn>,
     _marker: PhantomData<M>,
 }
 
-impl<M: DistanceMetric<f64, DIMENSIONS>> Knn<M> {
+// by hand, deriving would require the metric to be `Clone`, which kiddo's
+// metrics aren't
+impl<M, L: Label, const D: usize, T: Scalar> Clone for Knn<M, L, D, T>
+where
+    M: DistanceMetric<T, D> + MetricKind,
+{
+    fn clone(&self) -> Self {
+        Self {
+            k: self.k,
+            radius: self.radius,
+            kernel: Arc::clone(&self.kernel),
+            voting: self.voting,
+            window: self.window,
+            bandwidth: self.bandwidth,
+            kd_tree: self.kd_tree.clone(),
+            data: self.data.clone(),
+            weights: self.weights.clone(),
+            removed: self.removed.clone(),
+            live_points: self.live_points,
+            classes: self.classes.clone(),
+            class_bias: self.class_bias.clone(),
+            tie_break: self.tie_break,
+            smoothing: self.smoothing,
+            kernel_check: self.kernel_check,
+            warnings: self.warnings.clone(),
+            backend: self.backend,
+            instrumented: self.instrumented,
+            canonical_order: self.canonical_order,
+            query_stats: self.query_stats.clone(),
+            whitening: self.whitening.clone(),
+            registered_metric: self.registered_metric.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<M, L: Label, const D: usize, T: Scalar> Knn<M, L, D, T>
+where
+    M: DistanceMetric<T, D> + MetricKind,
+{
+    /// Shorthand for [`KnnBuilder::build`], failing the same way when the
+    /// window lacks its parameter
+    pub fn with_params(
+        k: usize,
+        radius: f64,
+        window: &WindowType,
+        kernel: impl Kernel + 'static,
+    ) -> Result<Self, KnnError> {
+        KnnBuilder::new()
+            .k(k)
+            .radius(radius)
+            .window(*window)
+            .kernel(kernel)
+            .build()
+    }
+
+    #[deprecated(note = "the tree is sized from the data passed to `fit`, use `Knn::with_params`")]
+    #[allow(deprecated)]
     pub fn new(
         k: usize,
         radius: f64,
         window: &WindowType,
-        kernel: fn(f64) -> f64,
+        kernel: impl Kernel + 'static,
         capacity: usize,
-    ) -> Self {
-        Knn {
-            k,
-            radius,
-            kernel,
-            window: *window,
-            kd_tree: KdTree::with_capacity(capacity),
-            data: Vec::new(),
-            weights: Vec::new(),
-            _marker: PhantomData,
-        }
+    ) -> Result<Self, KnnError> {
+        KnnBuilder::new()
+            .k(k)
+            .radius(radius)
+            .window(*window)
+            .kernel(kernel)
+            .capacity(capacity)
+            .build()
     }
 
-    pub fn fit(&mut self, data: Vec<Data>, weights: Option<Vec<f64>>) {
+    /// With `normalize_weights` the sample weights are rescaled to a mean of
+    /// 1.0, so their scale matches the unweighted case
+    pub fn fit(
+        &mut self,
+        data: Vec<Data<L, D, T>>,
+        weights: Option<Vec<f64>>,
+        normalize_weights: bool,
+    ) -> Result<(), KnnError> {
+        if let Some(weights) = &weights {
+            if weights.len() != data.len() {
+                return Err(KnnError::WeightLengthMismatch {
+                    expected: data.len(),
+                    got: weights.len(),
+                });
+            }
+
+            if let Some((index, &weight)) = weights
+                .iter()
+                .enumerate()
+                .find(|(_, weight)| !is_valid_weight(**weight))
+            {
+                return Err(KnnError::InvalidWeight { index, weight });
+            }
+        }
+
+        for (index, data_point) in data.iter().enumerate() {
+            check_finite(&data_point.features, Some(index))?;
+        }
+        let bucket_size = fitting_bucket(
+            self.bucket_size(),
+            max_shared(data.iter().map(|data_point| &data_point.features)),
+        )?;
+
+        self.whitening = None;
         self.data = data;
         self.weights = weights.unwrap_or_else(|| vec![1.0; self.data.len()]);
+        if normalize_weights {
+            let mean = self.weights.iter().sum::<f64>() / self.weights.len() as f64;
 
-        for (idx, data_point) in self.data.iter().enumerate() {
-            self.kd_tree.add(&data_point.features, idx);
+            // all-zero weights have no scale to normalize
+            if mean > 0.0 {
+                for weight in &mut self.weights {
+                    *weight /= mean;
+                }
+            }
         }
-    }
+        if self.canonical_order {
+            self.sort_canonically();
+        }
+        self.removed = vec![false; self.data.len()];
+        self.live_points = self.data.len();
+        // a refit must not keep the previous fit's points in the tree
+        self.kd_tree = Tree::with_capacity(bucket_size, self.data.len());
+        self.rebuild_tree();
+
+        self.classes = self
+            .data
+            .iter()
+            .map(|data_point| data_point.label)
+            .collect();
+        self.classes.sort_unstable();
+        self.classes.dedup();
 
-    pub fn predict(&self, x: &[f64; DIMENSIONS]) -> Result<Diagnosis, Box<dyn Error>> {
-        let (kernel_distances, targets, weights) = self.predict_with_neighbors(x);
+        Ok(())
+    }
 
-        if targets.is_empty() || weights.is_empty() {
-            return Err("no neighbors found for prediction".into());
+    /// Inserts a single training point into the tree without refitting, so
+    /// points arriving one at a time predict the same as one [`Knn::fit`] on
+    /// all of them. `point` is in the original feature space even when the
+    /// model is whitened
+    pub fn add(&mut self, point: Data<L, D, T>, weight: Option<f64>) -> Result<(), KnnError> {
+        let weight = weight.unwrap_or(1.0);
+        if !is_valid_weight(weight) {
+            return Err(KnnError::InvalidWeight {
+                index: self.data.len(),
+                weight,
+            });
         }
 
-        let predicted_class = Self::predict_class(&kernel_distances, &targets, &weights);
-        Ok(predicted_class)
-    }
+        check_finite(&point.features, Some(self.data.len()))?;
 
-    fn predict_class(
-        kernel_distances: &[f64],
-        targets: &[Diagnosis],
-        weights: &[f64],
-    ) -> Diagnosis {
-        let mut class_scores: HashMap<Diagnosis, f64> = HashMap::new();
+        let features = self.whitened(&point.features);
+        let shared = (0..D)
+            .map(|axis| {
+                self.iter()
+                    .filter(|data_point| data_point.features[axis] == features[axis])
+                    .count()
+                    + 1
+            })
+            .max()
+            .unwrap_or(0);
+        let bucket_size = fitting_bucket(self.bucket_size(), shared)?;
+        if bucket_size != self.bucket_size() {
+            self.kd_tree = Tree::with_capacity(bucket_size, self.data.len() + 1);
+            self.rebuild_tree();
+        }
+
+        self.kd_tree.add(&features, self.data.len());
+        self.data.push(Data {
+            features,
+            label: point.label,
+        });
+        self.weights.push(weight);
+        self.removed.push(false);
+        self.live_points += 1;
 
-        for (i, target) in targets.iter().enumerate() {
-            let weighted_score = kernel_distances[i] * weights[i];
-            *class_scores.entry(*target).or_insert(0.0) += weighted_score;
+        if let Err(position) = self.classes.binary_search(&point.label) {
+            self.classes.insert(position, point.label);
         }
 
-        class_scores
-            .into_iter()
-            .max_by(|first, second| first.1.partial_cmp(&second.1).unwrap())
-            .map(|(class, _)| class)
-            .unwrap()
+        Ok(())
     }
 
-    fn predict_with_neighbors(
-        &self,
-        x: &[f64; DIMENSIONS],
-    ) -> (Vec<f64>, Vec<Diagnosis>, Vec<f64>) {
-        let (distances, indices): (Vec<f64>, Vec<usize>) = match self.window {
-            WindowType::Fixed => self.kd_tree.within::<M>(x, self.radius.powi(2)),
-            WindowType::Unfixed => self.kd_tree.nearest_n::<M>(x, self.k),
+    /// Drops a training point from the search. Its slot in [`Knn::data`] is
+    /// kept so the indices of every other point stay valid
+    pub fn remove(&mut self, index: usize) -> Result<(), KnnError> {
+        if !self.is_live(index) {
+            return Err(KnnError::UnknownPoint(index));
         }
-        .into_iter()
-        .map(|neighbour| (neighbour.distance.sqrt(), neighbour.item))
-        .unzip();
 
-        let mut adjusted_distances = distances.clone();
-        let mut weights = Vec::new();
-        let mut targets = Vec::new();
+        self.kd_tree.remove(&self.data[index].features, index);
+        self.removed[index] = true;
+        self.live_points -= 1;
 
-        match self.window {
-            WindowType::Fixed => {
-                adjusted_distances
-                    .iter_mut()
-                    .for_each(|dist| *dist /= self.radius);
-            }
-            WindowType::Unfixed => {
-                let adjusted_distance = *adjusted_distances.last().unwrap();
-                adjusted_distances
-                    .iter_mut()
-                    .for_each(|distance| *distance /= adjusted_distance);
+        self.classes = self.iter().map(|data_point| data_point.label).collect();
+        self.classes.sort_unstable();
+        self.classes.dedup();
+
+        Ok(())
+    }
+
+    /// Keeps only the points for which `keep` returns true given the point
+    /// and its weight, then rebuilds the tree. Surviving points are
+    /// renumbered in their original order
+    pub fn retain(&mut self, mut keep: impl FnMut(&Data<L, D, T>, f64) -> bool) {
+        let (data, weights): (Vec<Data<L, D, T>>, Vec<f64>) = self
+            .data
+            .iter()
+            .zip(&self.weights)
+            .zip(&self.removed)
+            .filter(|&((data_point, &weight), removed)| !removed && keep(data_point, weight))
+            .map(|((data_point, &weight), _)| (*data_point, weight))
+            .unzip();
+
+        self.removed = vec![false; data.len()];
+        self.live_points = data.len();
+        self.data = data;
+        self.weights = weights;
+
+        self.classes = self
+            .data
+            .iter()
+            .map(|data_point| data_point.label)
+            .collect();
+        self.classes.sort_unstable();
+        self.classes.dedup();
+
+        self.rebuild_tree();
+    }
+
+    /// Drops every point with a weight below `min_weight`, e.g. the points
+    /// LOWESS found to be misclassified
+    pub fn prune_by_weight(&mut self, min_weight: f64) {
+        self.retain(|_, weight| weight >= min_weight);
+    }
+
+    fn rebuild_tree(&mut self) {
+        self.kd_tree = Tree::with_capacity(self.kd_tree.bucket_size(), self.data.len());
+
+        for (idx, data_point) in self.data.iter().enumerate() {
+            if !self.removed[idx] {
+                self.kd_tree.add(&data_point.features, idx);
             }
         }
+    }
+
+    fn is_live(&self, index: usize) -> bool {
+        self.removed.get(index).is_some_and(|removed| !removed)
+    }
+
+    /// Number of training points, not counting removed ones
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.live_points
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Fits on `data` whitened with PCA, queries are whitened with the same
+    /// transform until the next [`Knn::fit`]
+    pub fn with_pca_whitening(&mut self, data: &[Data<L, D, T>]) -> Result<(), KnnError> {
+        let features: Vec<[f64; D]> = data
+            .iter()
+            .map(|data_point| data_point.features.map(Into::into))
+            .collect();
+        let whitening = Whitening::fit(&features).ok_or_else(|| {
+            KnnError::InvalidInput("whitening needs at least two points".to_string())
+        })?;
+
+        let whitened = data
+            .iter()
+            .map(|data_point| Data {
+                features: whitening
+                    .apply(&data_point.features.map(Into::into))
+                    .map(T::from_f64),
+                label: data_point.label,
+            })
+            .collect();
+
+        self.fit(whitened, None, false)?;
+        self.whitening = Some(whitening);
+
+        Ok(())
+    }
+
+    /// `x` in the space the training points are stored in, unchanged unless
+    /// the model was fitted with [`Knn::with_pca_whitening`]
+    pub(crate) fn whitened(&self, x: &[T; D]) -> [T; D] {
+        self.whitening.as_ref().map_or(*x, |whitening| {
+            whitening.apply(&x.map(Into::into)).map(T::from_f64)
+        })
+    }
+
+    /// Classes scoring within `1e-12` of the best are tied and resolved by
+    /// [`Knn::tie_break`], by default the class of the nearest tied
+    /// neighbour, so equal scores never depend on hash order. Fails with
+    /// [`KnnError::NotFitted`] while the model has no points
+    pub fn predict(&self, x: &[T; D]) -> Result<L, KnnError> {
+        let neighbours = self.predict_with_neighbors(x)?;
+
+        if neighbours.is_empty() {
+            return Err(self.no_neighbors());
+        }
 
-        for &index in &indices {
-            targets.push(self.data[index].label);
-            weights.push(self.weights[index]);
+        self.predict_class(&neighbours)
+    }
+
+    /// [`Knn::predict`] for a model with a `Fixed` window, with the empty
+    /// window as its own error rather than one of the [`KnnError`]s. Other
+    /// windows fail with [`WindowError::Prediction`]
+    pub fn predict_fixed(&self, x: &[T; D]) -> Result<L, WindowError> {
+        if !matches!(self.window, WindowType::Fixed) {
+            return Err(WindowError::Prediction(KnnError::InvalidInput(format!(
+                "predict_fixed needs a fixed window, got {:?}",
+                self.window
+            ))));
         }
 
-        let kernel_distances: Vec<f64> = adjusted_distances
+        self.predict(x).map_err(|error| match error {
+            KnnError::NoNeighborsInRadius { .. } => WindowError::Empty,
+            error => WindowError::Prediction(error),
+        })
+    }
+
+    /// Fraction of `test_data` predicted correctly, points that can't be
+    /// predicted count as wrong. Zero for an empty test set
+    #[must_use]
+    pub fn score(&self, test_data: &[Data<L, D, T>]) -> f64 {
+        let features: Vec<[T; D]> = test_data
             .iter()
-            .map(|&dist| (self.kernel)(dist))
+            .map(|test_point| test_point.features)
             .collect();
 
-        (kernel_distances, targets, weights)
+        let correct_predictions = self
+            .predict_batch(&features)
+            .into_iter()
+            .zip(test_data)
+            .filter(|(prediction, actual)| match prediction {
+                Ok(prediction) => *prediction == actual.label,
+                Err(_) => false,
+            })
+            .count();
+
+        ratio(correct_predictions, test_data.len())
+    }
+
+    /// Drop in [`Knn::score`] on `data` when each feature's column is shuffled
+    /// across the points, the larger the more the model relies on it. Can be
+    /// slightly negative for features that don't matter
+    #[must_use]
+    pub fn permutation_importance(&self, data: &[Data<L, D, T>], seed: u64) -> [f64; D] {
+        let baseline = self.score(data);
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        std::array::from_fn(|feature| {
+            let mut column: Vec<T> = data
+                .iter()
+                .map(|data_point| data_point.features[feature])
+                .collect();
+            column.shuffle(&mut rng);
+
+            let permuted: Vec<Data<L, D, T>> = data
+                .iter()
+                .zip(column)
+                .map(|(data_point, value)| {
+                    let mut permuted = *data_point;
+                    permuted.features[feature] = value;
+                    permuted
+                })
+                .collect();
+
+            baseline - self.score(&permuted)
+        })
+    }
+
+    /// [`Knn::predict`] as if training point `exclude_index` wasn't fitted,
+    /// for leave-one-out evaluation without rebuilding the tree
+    pub fn predict_excluding(&self, x: &[T; D], exclude_index: usize) -> Result<L, KnnError> {
+        let mut neighbours = Vec::new();
+        self.fill_neighbors(x, Some(exclude_index), &mut neighbours)?;
+
+        if neighbours.is_empty() {
+            return Err(self.no_neighbors());
+        }
+
+        self.predict_class(&neighbours)
+    }
+
+    /// Predictions in the same order as `xs`, reusing the neighbour buffers
+    /// between points
+    pub fn predict_batch(&self, xs: &[[T; D]]) -> Vec<Result<L, KnnError>> {
+        let mut neighbours = Vec::new();
+
+        xs.iter()
+            .map(|x| {
+                self.fill_neighbors(x, None, &mut neighbours)?;
+
+                if neighbours.is_empty() {
+                    return Err(self.no_neighbors());
+                }
+
+                self.predict_class(&neighbours)
+            })
+            .collect()
+    }
+
+    /// The training points in index order, skipping removed ones
+    pub fn iter(&self) -> Iter<'_, L, D, T> {
+        Iter {
+            points: self.data.iter().zip(&self.removed),
+        }
+    }
+
+    /// Every training point by index, including removed ones
+    #[must_use]
+    pub fn data(&self) -> &[Data<L, D, T>] {
+        &self.data
+    }
+
+    /// Distance from every training point to its k-th nearest other point
+    #[must_use]
+    pub fn k_distances(&self, k: usize) -> Vec<f64> {
+        self.iter()
+            .filter_map(|data_point| {
+                self.kd_tree
+                    .nearest_n::<M>(&data_point.features, k + 1)
+                    .last()
+                    .map(|neighbour| M::finalize(neighbour.distance.into()))
+            })
+            .collect()
+    }
+
+    /// Minimum, quartiles and maximum of [`Knn::k_distances`], NaN when the
+    /// model is empty
+    #[must_use]
+    pub fn neighbor_distance_quantiles(&self, k: usize) -> [f64; 5] {
+        let mut distances = self.k_distances(k);
+        distances.sort_by(f64::total_cmp);
+
+        [0.0, 0.25, 0.5, 0.75, 1.0].map(|q| quantile(&distances, q))
+    }
+
+    /// The `q`-quantile of every training point's distance to its nearest
+    /// other point, a starting radius for [`WindowType::Fixed`]. NaN when the
+    /// model is empty
+    #[must_use]
+    pub fn suggest_radius(&self, q: f64) -> f64 {
+        let mut distances = self.k_distances(1);
+        distances.sort_by(f64::total_cmp);
+
+        quantile(&distances, q)
+    }
+
+    /// Levina-Bickel maximum-likelihood estimate of the intrinsic
+    /// dimensionality from the `k` nearest other points of every training
+    /// point, averaging the per-point inverses to reduce bias. NaN when
+    /// `k < 2` or every neighbourhood is degenerate
+    #[must_use]
+    pub fn intrinsic_dimension(&self, k: usize) -> f64 {
+        if k < 2 {
+            return f64::NAN;
+        }
+
+        let inverse_estimates: Vec<f64> = self
+            .iter()
+            .filter_map(|data_point| {
+                let distances: Vec<f64> = self
+                    .kd_tree
+                    .nearest_n::<M>(&data_point.features, k + 1)
+                    .iter()
+                    .skip(1)
+                    .map(|neighbour| M::finalize(neighbour.distance.into()))
+                    .collect();
+
+                let &farthest = distances.last()?;
+                if distances.len() < k || distances[0] <= 0.0 {
+                    return None;
+                }
+
+                let log_ratios: f64 = distances[..k - 1]
+                    .iter()
+                    .map(|distance| (farthest / distance).ln())
+                    .sum();
+
+                Some(log_ratios / (k - 1) as f64)
+            })
+            .collect();
+
+        let mean_inverse = inverse_estimates.iter().sum::<f64>() / inverse_estimates.len() as f64;
+
+        if mean_inverse > 0.0 {
+            1.0 / mean_inverse
+        } else {
+            f64::NAN
+        }
+    }
+
+    #[must_use]
+    pub fn nearest_distance(&self, x: &[T; D]) -> Option<f64> {
+        self.nearest(x).ok().map(|(_, distance, _)| distance)
+    }
+
+    /// Index, distance and label of the closest training point whatever the
+    /// window. Of several equidistant points the one the kd-tree reaches
+    /// first wins, which is not necessarily the lowest index
+    pub fn nearest(&self, x: &[T; D]) -> Result<(usize, f64, L), KnnError> {
+        if self.is_empty() {
+            return Err(KnnError::NotFitted);
+        }
+        check_finite(x, None)?;
+
+        let nearest = self.kd_tree.nearest_one::<M>(&self.whitened(x));
+
+        Ok((
+            nearest.item,
+            M::finalize(nearest.distance.into()),
+            self.data[nearest.item].label,
+        ))
+    }
+
+    #[must_use]
+    pub fn classes(&self) -> &[L] {
+        &self.classes
+    }
+
+    /// Class probabilities in the order of [`Knn::classes`], with zeros for
+    /// classes absent from the neighbourhood
+    pub fn predict_proba_dense(&self, x: &[T; D]) -> Result<Vec<f64>, KnnError> {
+        let probabilities = self.predict_proba(x)?;
+
+        Ok(self
+            .classes
+            .iter()
+            .map(|class| probabilities.get(class).copied().unwrap_or(0.0))
+            .collect())
+    }
+
+    /// Kernel-weighted class scores normalized to sum to 1.0, uniform over the
+    /// neighbour labels when every kernel weight is zero
+    pub fn predict_proba(&self, x: &[T; D]) -> Result<BTreeMap<L, f64>, KnnError> {
+        let neighbours = self.predict_with_neighbors(x)?;
+
+        if neighbours.is_empty() {
+            return Err(self.no_neighbors());
+        }
+
+        Ok(self.normalized_scores(&neighbours))
+    }
+
+    /// [`Knn::predict_proba`] as a list, most probable class first and tied
+    /// classes in their natural order
+    pub fn ranked_scores(&self, x: &[T; D]) -> Result<Vec<(L, f64)>, KnnError> {
+        let mut scores: Vec<(L, f64)> = self.predict_proba(x)?.into_iter().collect();
+        scores.sort_by(|first, second| second.1.total_cmp(&first.1).then(first.0.cmp(&second.0)));
+
+        Ok(scores)
+    }
+
+    fn normalized_scores(&self, neighbours: &[Neighbor<L>]) -> BTreeMap<L, f64> {
+        let mut class_scores = Self::class_scores(neighbours);
+
+        if self.smoothing > 0.0 {
+            for class in &self.classes {
+                *class_scores.entry(*class).or_insert(0.0) += self.smoothing;
+            }
+        }
+
+        let total: f64 = class_scores.values().sum();
+
+        if total > 0.0 {
+            for score in class_scores.values_mut() {
+                *score /= total;
+            }
+        } else {
+            // all neighbours got zero weight, so every present label is equally likely
+            let probability = 1.0 / class_scores.len() as f64;
+            for score in class_scores.values_mut() {
+                *score = probability;
+            }
+        }
+
+        class_scores
+    }
+
+    pub fn predict_with_provenance(&self, x: &[T; D]) -> Result<(L, Provenance), KnnError> {
+        let neighbours = self.predict_with_neighbors(x)?;
+
+        if neighbours.is_empty() {
+            return Err(self.no_neighbors());
+        }
+
+        let predicted_class = self.predict_class(&neighbours)?;
+        let provenance = Provenance {
+            neighbors: neighbours.iter().map(|neighbour| neighbour.index).collect(),
+            kernel_weights: neighbours
+                .iter()
+                .map(|neighbour| neighbour.kernel_weight)
+                .collect(),
+            weights: neighbours
+                .iter()
+                .map(|neighbour| neighbour.sample_weight)
+                .collect(),
+            kernel: match self.voting {
+                VoteWeighting::Kernel => self.kernel.name(),
+                VoteWeighting::InverseDistance { .. } => "inverse distance",
+                VoteWeighting::Uniform => "uniform vote",
+            },
+            metric: if self.registered_metric.is_some() {
+                "registered"
+            } else {
+                M::name()
+            },
+            window: self.window,
+        };
+
+        Ok((predicted_class, provenance))
+    }
+
+    pub fn neighbors(&self, x: &[T; D]) -> (Vec<f64>, Vec<usize>) {
+        let (mut distances, mut indices) = self.neighbors_excluding(x, None);
+
+        if let WindowType::Adaptive { m } = self.window {
+            distances.truncate(m);
+            indices.truncate(m);
+        }
+
+        (distances, indices)
+    }
+
+    /// [`Knn::neighbors`] as if the `exclude` training point wasn't fitted.
+    /// The `Adaptive` window's bandwidth point is kept as the last one
+    fn neighbors_excluding(&self, x: &[T; D], exclude: Option<usize>) -> (Vec<f64>, Vec<usize>) {
+        let x = &self.whitened(x);
+
+        if let Some(metric) = &self.registered_metric {
+            // registered metrics already report final distances
+            let x = x.map(Into::into);
+            let mut neighbours =
+                self.brute_force(|features| metric(&x, &features.map(Into::into)), exclude);
+            self.apply_window(&mut neighbours, |radius| radius);
+
+            return neighbours
+                .into_iter()
+                .map(|neighbour| (neighbour.distance, neighbour.item))
+                .unzip();
+        }
+
+        let neighbours = if self.instrumented {
+            let before = evaluations();
+            let neighbours = self.query::<Counting<M>>(x, exclude);
+            let after = evaluations();
+
+            let stats = &self.query_stats;
+            stats.queries.fetch_add(1, Ordering::Relaxed);
+            stats
+                .nodes_visited
+                .fetch_add(after.dist1 - before.dist1, Ordering::Relaxed);
+            stats
+                .points_examined
+                .fetch_add(after.dist - before.dist, Ordering::Relaxed);
+
+            neighbours
+        } else {
+            self.query::<M>(x, exclude)
+        };
+
+        neighbours
+            .into_iter()
+            .map(|neighbour| (M::finalize(neighbour.distance), neighbour.item))
+            .unzip()
+    }
+
+    /// [`Knn::predict_with_neighbors`], empty when the query fails
+    pub fn kneighbors(&self, x: &[T; D]) -> Vec<Neighbor<L>> {
+        self.predict_with_neighbors(x).unwrap_or_default()
+    }
+
+    /// `Q` is `M` or a wrapper reporting in the same units, radii are
+    /// converted with `M`
+    fn query<Q: DistanceMetric<T, D>>(
+        &self,
+        x: &[T; D],
+        exclude: Option<usize>,
+    ) -> Vec<NearestNeighbour<f64, usize>> {
+        // one spare neighbour makes up for the excluded one
+        let spare = usize::from(exclude.is_some());
+        let is_kept = |neighbour: &NearestNeighbour<T, usize>| Some(neighbour.item) != exclude;
+
+        match self.backend {
+            Backend::KdTree => match self.window {
+                WindowType::Fixed => {
+                    let radius = T::from_f64(M::raw(self.radius));
+                    let mut neighbours = self.kd_tree.within::<Q>(x, radius);
+                    neighbours.retain(is_kept);
+
+                    neighbours
+                }
+                WindowType::Unfixed => {
+                    let mut neighbours = self
+                        .kd_tree
+                        .nearest_n::<Q>(x, (self.k + spare).min(self.len()));
+                    neighbours.retain(is_kept);
+                    neighbours.truncate(self.k);
+
+                    neighbours
+                }
+                WindowType::Capped { radius, max_k } => {
+                    let radius = T::from_f64(M::raw(radius));
+                    let mut neighbours = self
+                        .kd_tree
+                        .nearest_n::<Q>(x, (max_k + spare).min(self.len()));
+                    neighbours.retain(is_kept);
+                    neighbours.truncate(max_k);
+                    neighbours.retain(|neighbour| neighbour.distance <= radius);
+
+                    neighbours
+                }
+                WindowType::Adaptive { m } => {
+                    let mut neighbours = self
+                        .kd_tree
+                        .nearest_n::<Q>(x, (m + 1 + spare).min(self.len()));
+                    neighbours.retain(is_kept);
+                    neighbours.truncate(m + 1);
+
+                    neighbours
+                }
+            }
+            .into_iter()
+            .map(|neighbour| NearestNeighbour {
+                distance: neighbour.distance.into(),
+                item: neighbour.item,
+            })
+            .collect(),
+            Backend::BruteForce => {
+                let mut neighbours =
+                    self.brute_force(|features| Q::dist(x, features).into(), exclude);
+                self.apply_window(&mut neighbours, M::raw);
+
+                neighbours
+            }
+        }
+    }
+
+    /// Every live point but `exclude`, nearest first
+    fn brute_force(
+        &self,
+        distance: impl Fn(&[T; D]) -> f64,
+        exclude: Option<usize>,
+    ) -> Vec<NearestNeighbour<f64, usize>> {
+        let mut neighbours: Vec<NearestNeighbour<f64, usize>> = self
+            .data
+            .iter()
+            .enumerate()
+            .filter(|&(item, _)| self.is_live(item) && Some(item) != exclude)
+            .map(|(item, data_point)| NearestNeighbour {
+                distance: distance(&data_point.features),
+                item,
+            })
+            .collect();
+        neighbours.sort_by(|first, second| first.distance.total_cmp(&second.distance));
+
+        neighbours
+    }
+
+    /// Keeps the sorted `neighbours` inside the window, `to_raw` converts the
+    /// radius to the units of their distances
+    fn apply_window(
+        &self,
+        neighbours: &mut Vec<NearestNeighbour<f64, usize>>,
+        to_raw: fn(f64) -> f64,
+    ) {
+        match self.window {
+            WindowType::Fixed => {
+                let radius = to_raw(self.radius);
+                neighbours.retain(|neighbour| neighbour.distance <= radius);
+            }
+            WindowType::Unfixed => neighbours.truncate(self.k),
+            WindowType::Capped { radius, max_k } => {
+                let radius = to_raw(radius);
+                neighbours.truncate(max_k);
+                neighbours.retain(|neighbour| neighbour.distance <= radius);
+            }
+            WindowType::Adaptive { m } => neighbours.truncate(m + 1),
+        }
+    }
+
+    /// Answers neighbour queries with the registry's `name` metric by brute
+    /// force instead of `M`. Other statistics such as
+    /// [`Knn::k_distances`] keep using `M`
+    pub fn use_registered_metric(
+        &mut self,
+        registry: &MetricRegistry,
+        name: &str,
+    ) -> Result<(), KnnError> {
+        let metric = registry
+            .get(name)
+            .ok_or_else(|| KnnError::InvalidInput(format!("no metric registered as {name}")))?;
+        self.registered_metric = Some(metric);
+
+        Ok(())
+    }
+
+    /// Goes back to answering neighbour queries with `M`
+    pub fn clear_registered_metric(&mut self) {
+        self.registered_metric = None;
+    }
+
+    #[must_use]
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    pub fn set_backend(&mut self, backend: Backend) {
+        self.backend = backend;
+    }
+
+    #[must_use]
+    pub fn bucket_size(&self) -> BucketSize {
+        self.kd_tree.bucket_size()
+    }
+
+    /// Rebuilds the tree with `bucket_size` points per leaf, or the smallest
+    /// larger size when more points than that share a value on one axis
+    pub fn set_bucket_size(&mut self, bucket_size: BucketSize) {
+        // the live points already fit the current size, so some size fits
+        let bucket_size = bucket_size
+            .fitting(max_shared(
+                self.iter().map(|data_point| &data_point.features),
+            ))
+            .unwrap_or(BucketSize::B64);
+        self.kd_tree = Tree::with_capacity(bucket_size, self.data.len());
+        self.rebuild_tree();
+    }
+
+    /// Makes `fit` sort the points by features, then label, before building
+    /// the tree, so that which of several equidistant points the queries
+    /// return no longer depends on the input order. Indices into
+    /// [`Knn::data`] then refer to the sorted order
+    pub fn set_canonical_order(&mut self, canonical_order: bool) {
+        self.canonical_order = canonical_order;
+    }
+
+    fn sort_canonically(&mut self) {
+        let mut order: Vec<usize> = (0..self.data.len()).collect();
+        order.sort_by(|&first, &second| {
+            let (first, second) = (&self.data[first], &self.data[second]);
+
+            first
+                .features
+                .iter()
+                .zip(&second.features)
+                .map(|(&a_val, &b_val)| a_val.into().total_cmp(&b_val.into()))
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then(first.label.cmp(&second.label))
+        });
+
+        self.data = order.iter().map(|&index| self.data[index]).collect();
+        self.weights = order.iter().map(|&index| self.weights[index]).collect();
+    }
+
+    /// Enables counting of nodes visited and points examined by every query
+    pub fn set_instrumented(&mut self, instrumented: bool) {
+        self.instrumented = instrumented;
+    }
+
+    #[must_use]
+    pub fn query_stats(&self) -> QueryStats {
+        self.query_stats.get()
+    }
+
+    pub fn reset_query_stats(&mut self) {
+        self.query_stats = QueryStatsCounter::default();
+    }
+
+    #[must_use]
+    pub fn bandwidth(&self) -> Bandwidth {
+        self.bandwidth
+    }
+
+    pub fn set_bandwidth(&mut self, bandwidth: Bandwidth) {
+        self.bandwidth = bandwidth;
+    }
+
+    #[must_use]
+    pub fn smoothing(&self) -> f64 {
+        self.smoothing
+    }
+
+    /// Additive (Laplace) smoothing: `alpha` is added to the score of every
+    /// training class before normalization, so no probability is exactly 0 or 1
+    pub fn set_smoothing(&mut self, alpha: f64) {
+        self.smoothing = alpha;
+    }
+
+    #[must_use]
+    pub fn kernel_check(&self) -> KernelCheck {
+        self.kernel_check
+    }
+
+    pub fn set_kernel_check(&mut self, kernel_check: KernelCheck) {
+        self.kernel_check = kernel_check;
+    }
+
+    /// Number of kernel outputs replaced with zero by [`KernelCheck::Lenient`]
+    #[must_use]
+    pub fn warnings(&self) -> usize {
+        self.warnings.0.load(Ordering::Relaxed)
+    }
+
+    #[must_use]
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    /// The query parameters below only change how the fitted tree is
+    /// searched, so none of them refit. `set_k`, `set_radius` and
+    /// `set_window` fail like [`KnnBuilder::build`] when the window would
+    /// lack its parameter, leaving the model unchanged
+    pub fn set_k(&mut self, k: usize) -> Result<(), KnnError> {
+        check_window(self.window, k, self.radius)?;
+        self.k = k;
+
+        Ok(())
+    }
+
+    #[must_use]
+    pub fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    /// Any window can be switched to `Fixed` later, so the radius has to be
+    /// positive and finite whatever the current window
+    pub fn set_radius(&mut self, radius: f64) -> Result<(), KnnError> {
+        check_window(WindowType::Fixed, self.k, radius)?;
+        self.radius = radius;
+
+        Ok(())
+    }
+
+    #[must_use]
+    pub fn window(&self) -> WindowType {
+        self.window
+    }
+
+    pub fn set_window(&mut self, window: WindowType) -> Result<(), KnnError> {
+        check_window(window, self.k, self.radius)?;
+        self.window = window;
+
+        Ok(())
+    }
+
+    pub fn set_kernel(&mut self, kernel: impl Kernel + 'static) {
+        self.kernel = Arc::new(kernel);
+    }
+
+    #[must_use]
+    pub fn vote_weighting(&self) -> VoteWeighting {
+        self.voting
+    }
+
+    /// Fails like [`KnnBuilder::build`] on an invalid epsilon, leaving the
+    /// weighting unchanged
+    pub fn set_vote_weighting(&mut self, voting: VoteWeighting) -> Result<(), KnnError> {
+        check_voting(voting)?;
+        self.voting = voting;
+
+        Ok(())
+    }
+
+    fn vote(&self, distance: f64, normalized_distance: f64) -> Result<f64, KnnError> {
+        match self.voting {
+            VoteWeighting::Kernel => self.checked_kernel(normalized_distance),
+            VoteWeighting::InverseDistance { epsilon } => Ok((distance + epsilon).recip()),
+            VoteWeighting::Uniform => Ok(1.0),
+        }
+    }
+
+    fn checked_kernel(&self, distance: f64) -> Result<f64, KnnError> {
+        let value = self.kernel.eval(distance);
+
+        if self.kernel_check == KernelCheck::Off || (value.is_finite() && value >= 0.0) {
+            return Ok(value);
+        }
+
+        if self.kernel_check == KernelCheck::Strict {
+            return Err(KnnError::InvalidInput(format!(
+                "kernel returned {value} for distance {distance}"
+            )));
+        }
+
+        self.warnings.0.fetch_add(1, Ordering::Relaxed);
+        Ok(0.0)
+    }
+
+    #[must_use]
+    pub fn class_bias(&self) -> &BTreeMap<L, f64> {
+        &self.class_bias
+    }
+
+    pub fn set_class_bias(&mut self, class: L, bias: f64) {
+        self.class_bias.insert(class, bias);
+    }
+
+    /// Picks per-class biases, added to the class probabilities, one class at
+    /// a time by a 1-D search that maximizes the macro F1 score on
+    /// `validation`, so a rare class counts as much as a common one. Points
+    /// the model can't score are left out
+    pub fn fit_class_bias(&mut self, validation: &[Data<L, D, T>]) {
+        const BIAS_STEPS: i32 = 50;
+
+        let (actuals, class_scores): (Vec<L>, Vec<BTreeMap<L, f64>>) = validation
+            .iter()
+            .filter_map(|data_point| {
+                let scores = self.predict_proba(&data_point.features).ok()?;
+
+                Some((data_point.label, scores))
+            })
+            .unzip();
+
+        for class in self.classes.clone() {
+            let mut best_bias = self.class_bias.get(&class).copied().unwrap_or(0.0);
+            let mut best_f1 = self.biased_f1_macro(&actuals, &class_scores);
+
+            for step in -BIAS_STEPS..=BIAS_STEPS {
+                let bias = f64::from(step) / f64::from(BIAS_STEPS);
+                self.class_bias.insert(class, bias);
+
+                let f1 = self.biased_f1_macro(&actuals, &class_scores);
+                if f1 > best_f1 {
+                    best_f1 = f1;
+                    best_bias = bias;
+                }
+            }
+
+            self.class_bias.insert(class, best_bias);
+        }
+    }
+
+    /// Macro F1 of the biased predictions, leaving out the ties
+    /// [`TieBreak::Error`] refuses to break
+    fn biased_f1_macro(&self, actuals: &[L], class_scores: &[BTreeMap<L, f64>]) -> f64 {
+        let (actuals, predictions): (Vec<L>, Vec<L>) = actuals
+            .iter()
+            .zip(class_scores)
+            .filter_map(|(&actual, scores)| {
+                let prediction = self.best_class(scores.clone(), &[]).ok()?;
+
+                Some((actual, prediction))
+            })
+            .unzip();
+
+        f1_macro(&actuals, &predictions)
+    }
+
+    #[must_use]
+    pub fn tie_break(&self) -> TieBreak<L> {
+        self.tie_break
+    }
+
+    pub fn set_tie_break(&mut self, tie_break: TieBreak<L>) {
+        self.tie_break = tie_break;
+    }
+
+    fn predict_class(&self, neighbours: &[Neighbor<L>]) -> Result<L, KnnError> {
+        self.best_class(self.normalized_scores(neighbours), neighbours)
+    }
+
+    /// Ordered by label, so scores are summed and normalized in the same
+    /// order on every run and come out bit for bit the same. A NaN score,
+    /// only possible with [`KernelCheck::Off`], counts as no vote
+    fn class_scores(neighbours: &[Neighbor<L>]) -> BTreeMap<L, f64> {
+        let mut class_scores: BTreeMap<L, f64> = BTreeMap::new();
+
+        for neighbour in neighbours {
+            let weighted_score = neighbour.kernel_weight * neighbour.sample_weight;
+            *class_scores.entry(neighbour.label).or_insert(0.0) += weighted_score;
+        }
+
+        for score in class_scores.values_mut() {
+            if score.is_nan() {
+                *score = 0.0;
+            }
+        }
+
+        class_scores
+    }
+
+    /// `neighbours` are nearest first, used to break ties
+    fn best_class(
+        &self,
+        mut class_scores: BTreeMap<L, f64>,
+        neighbours: &[Neighbor<L>],
+    ) -> Result<L, KnnError> {
+        for (class, bias) in &self.class_bias {
+            if let Some(score) = class_scores.get_mut(class) {
+                *score += bias;
+            }
+        }
+
+        // NaN scores rank below everything: `f64::max` skips them and they
+        // are never within epsilon of the best
+        let best_score = class_scores
+            .values()
+            .copied()
+            .fold(f64::NEG_INFINITY, f64::max);
+
+        let mut tied: Vec<L> = class_scores
+            .into_iter()
+            .filter(|(_, score)| !score.is_nan() && best_score - score <= TIE_EPSILON)
+            .map(|(class, _)| class)
+            .collect();
+        tied.sort_unstable();
+
+        let Some(&smallest) = tied.first() else {
+            return Err(KnnError::NoNeighbors);
+        };
+        if tied.len() == 1 {
+            return Ok(smallest);
+        }
+
+        match self.tie_break {
+            TieBreak::PreferNearest => Ok(neighbours
+                .iter()
+                .map(|neighbour| neighbour.label)
+                .find(|label| tied.contains(label))
+                .unwrap_or(smallest)),
+            TieBreak::PreferLabel(label) if tied.contains(&label) => Ok(label),
+            TieBreak::PreferLabel(_) => Ok(smallest),
+            TieBreak::Error => Err(KnnError::Tie),
+        }
+    }
+
+    /// Error for a query with an empty neighbourhood, naming the radius when
+    /// the window has one
+    pub(crate) fn no_neighbors(&self) -> KnnError {
+        match self.window {
+            WindowType::Fixed => KnnError::NoNeighborsInRadius {
+                radius: self.radius,
+            },
+            WindowType::Capped { radius, .. } => KnnError::NoNeighborsInRadius { radius },
+            WindowType::Unfixed | WindowType::Adaptive { .. } => KnnError::NoNeighbors,
+        }
+    }
+
+    /// The neighbours `predict` votes with, nearest first. Empty when the
+    /// window holds no training points
+    pub fn predict_with_neighbors(&self, x: &[T; D]) -> Result<Vec<Neighbor<L>>, KnnError> {
+        let mut neighbours = Vec::new();
+        self.fill_neighbors(x, None, &mut neighbours)?;
+
+        Ok(neighbours)
+    }
+
+    fn fill_neighbors(
+        &self,
+        x: &[T; D],
+        exclude: Option<usize>,
+        neighbours: &mut Vec<Neighbor<L>>,
+    ) -> Result<(), KnnError> {
+        if self.is_empty() {
+            return Err(KnnError::NotFitted);
+        }
+        check_finite(x, None)?;
+
+        let (distances, indices) = self.neighbors_excluding(x, exclude);
+        // the adaptive window's bandwidth point has no normalized distance,
+        // so the zip leaves it out of the vote
+        let normalized = normalized_distances(&distances, self.window, self.radius, self.bandwidth);
+
+        neighbours.clear();
+
+        for ((distance, index), normalized_distance) in
+            distances.into_iter().zip(indices).zip(normalized)
+        {
+            neighbours.push(Neighbor {
+                index,
+                raw_distance: distance,
+                normalized_distance,
+                kernel_weight: self.vote(distance, normalized_distance)?,
+                sample_weight: self.weights[index],
+                label: self.data[index].label,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// What [`Knn::save`] writes: the hyperparameters and the live training
+/// points, the tree is rebuilt on load
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+struct SavedModel<L> {
+    k: usize,
+    radius: f64,
+    window: WindowType,
+    kernel: String,
+    voting: VoteWeighting,
+    bandwidth: Bandwidth,
+    tie_break: TieBreak<L>,
+    smoothing: f64,
+    /// A list rather than a map, JSON keys can only be strings
+    class_bias: Vec<(L, f64)>,
+    bucket_size: BucketSize,
+    canonical_order: bool,
+    kernel_check: KernelCheck,
+    backend: Backend,
+    features: Vec<Vec<f64>>,
+    labels: Vec<L>,
+    weights: Vec<f64>,
+}
+
+#[cfg(feature = "serde")]
+impl<M, L, const D: usize, T: Scalar> Knn<M, L, D, T>
+where
+    M: DistanceMetric<T, D> + MetricKind,
+    L: Label + Serialize + DeserializeOwned,
+{
+    /// Writes the model as JSON with every setting `predict` depends on.
+    /// Removed points are left out, so indices of the loaded model's points
+    /// can differ. Only the built-in kernels can be
+    /// saved, and models fitted with whitening or a registered metric can't
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        let kernel = self.kernel.name();
+        if kernel::by_name(kernel).is_none() {
+            return Err(KnnError::InvalidInput(format!("can't save the {kernel} kernel")).into());
+        }
+        if self.whitening.is_some() || self.registered_metric.is_some() {
+            return Err(KnnError::InvalidInput(
+                "can't save a model with whitening or a registered metric".to_string(),
+            )
+            .into());
+        }
+
+        let live: Vec<usize> = (0..self.data.len())
+            .filter(|&index| self.is_live(index))
+            .collect();
+
+        let model = SavedModel {
+            k: self.k,
+            radius: self.radius,
+            window: self.window,
+            kernel: kernel.to_string(),
+            voting: self.voting,
+            bandwidth: self.bandwidth,
+            tie_break: self.tie_break,
+            smoothing: self.smoothing,
+            class_bias: self
+                .class_bias
+                .iter()
+                .map(|(&class, &bias)| (class, bias))
+                .collect(),
+            bucket_size: self.bucket_size(),
+            canonical_order: self.canonical_order,
+            kernel_check: self.kernel_check,
+            backend: self.backend,
+            features: live
+                .iter()
+                .map(|&index| self.data[index].features.map(Into::into).to_vec())
+                .collect(),
+            labels: live.iter().map(|&index| self.data[index].label).collect(),
+            weights: live.iter().map(|&index| self.weights[index]).collect(),
+        };
+
+        serde_json::to_writer(BufWriter::new(File::create(path)?), &model)?;
+        Ok(())
+    }
+
+    /// Reads a model written by [`Knn::save`] and refits it
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        let model: SavedModel<L> = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+
+        let mut builder = KnnBuilder::new()
+            .k(model.k)
+            .radius(model.radius)
+            .window(model.window)
+            .voting(model.voting)
+            .bucket_size(model.bucket_size);
+        builder.kernel = kernel::by_name(&model.kernel)
+            .ok_or_else(|| KnnError::InvalidInput(format!("unknown kernel {}", model.kernel)))?;
+        let mut knn: Self = builder.build()?;
+        knn.set_bandwidth(model.bandwidth);
+        knn.set_tie_break(model.tie_break);
+        knn.set_smoothing(model.smoothing);
+        knn.class_bias = model.class_bias.into_iter().collect();
+        knn.set_canonical_order(model.canonical_order);
+        knn.set_kernel_check(model.kernel_check);
+        knn.set_backend(model.backend);
+
+        let data = model
+            .features
+            .into_iter()
+            .zip(model.labels)
+            .map(|(features, label)| {
+                let width = features.len();
+                let features: [f64; D] = features.try_into().map_err(|_| {
+                    KnnError::InvalidInput(format!("expected {D} features, got {width}"))
+                })?;
+
+                Ok(Data {
+                    features: features.map(T::from_f64),
+                    label,
+                })
+            })
+            .collect::<Result<Vec<_>, KnnError>>()?;
+        knn.fit(data, Some(model.weights), false)?;
+
+        Ok(knn)
+    }
+}
+
+impl<'a, M, L: Label, const D: usize, T: Scalar> IntoIterator for &'a Knn<M, L, D, T>
+where
+    M: DistanceMetric<T, D> + MetricKind,
+{
+    type Item = &'a Data<L, D, T>;
+    type IntoIter = Iter<'a, L, D, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over the live training points of a [`Knn`], see [`Knn::iter`]
+pub struct Iter<'a, L: Label, const D: usize, T: Scalar> {
+    points: std::iter::Zip<std::slice::Iter<'a, Data<L, D, T>>, std::slice::Iter<'a, bool>>,
+}
+
+impl<'a, L: Label, const D: usize, T: Scalar> Iterator for Iter<'a, L, D, T> {
+    type Item = &'a Data<L, D, T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.points
+            .find(|(_, removed)| !**removed)
+            .map(|(data_point, _)| data_point)
+    }
+}
+
+impl<M, const D: usize, T: Scalar> Knn<M, Diagnosis, D, T>
+where
+    M: DistanceMetric<T, D> + MetricKind,
+{
+    /// Predicts `Malignant` whenever its probability is at least `threshold`,
+    /// ignoring class biases. A threshold of 0.0 always predicts `Malignant`,
+    /// 1.0 only does so when every weighted neighbour is malignant
+    pub fn predict_with_threshold(
+        &self,
+        x: &[T; D],
+        threshold: f64,
+    ) -> Result<Diagnosis, KnnError> {
+        let probabilities = self.predict_proba(x)?;
+        let malignant = probabilities
+            .get(&Diagnosis::Malignant)
+            .copied()
+            .unwrap_or(0.0);
+
+        if malignant >= threshold {
+            Ok(Diagnosis::Malignant)
+        } else {
+            Ok(Diagnosis::Benign)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::ConfusionMatrix;
+    use kiddo::Manhattan;
+
+    fn labelled(points: &[([f64; 2], Diagnosis)]) -> Vec<Data<Diagnosis, 2>> {
+        points
+            .iter()
+            .map(|&(features, label)| Data { features, label })
+            .collect()
+    }
+
+    fn line() -> Vec<Data<Diagnosis, 2>> {
+        labelled(&[
+            ([0.0, 0.0], Diagnosis::Benign),
+            ([1.0, 0.0], Diagnosis::Benign),
+            ([2.0, 0.0], Diagnosis::Benign),
+            ([10.0, 0.0], Diagnosis::Malignant),
+            ([11.0, 0.0], Diagnosis::Malignant),
+        ])
+    }
+
+    #[test]
+    fn buckets_grow_when_more_points_than_fit_share_a_value() {
+        let duplicates = |count: usize| {
+            (0..count)
+                .map(|_| Data {
+                    features: [1.0, 1.0],
+                    label: Diagnosis::Benign,
+                })
+                .collect::<Vec<_>>()
+        };
+        let builder = || {
+            KnnBuilder::new()
+                .k(3)
+                .kernel(kernel::Uniform)
+                .bucket_size(BucketSize::B8)
+        };
+
+        let mut fitted: Knn<Manhattan, Diagnosis, 2> = builder().build().unwrap();
+        fitted.fit(duplicates(12), None, false).unwrap();
+        assert_eq!(fitted.bucket_size(), BucketSize::B16);
+        assert_eq!(fitted.predict(&[1.0, 1.0]), Ok(Diagnosis::Benign));
+
+        fitted.set_bucket_size(BucketSize::B8);
+        assert_eq!(fitted.bucket_size(), BucketSize::B16);
+
+        let mut added: Knn<Manhattan, Diagnosis, 2> = builder().build().unwrap();
+        for point in duplicates(12) {
+            added.add(point, None).unwrap();
+        }
+        assert_eq!(added.bucket_size(), BucketSize::B16);
+        assert_eq!(added.len(), 12);
+        assert_eq!(added.predict(&[1.0, 1.0]), Ok(Diagnosis::Benign));
+
+        let mut crowded: Knn<Manhattan, Diagnosis, 2> = builder().build().unwrap();
+        assert!(matches!(
+            crowded.fit(duplicates(64), None, false),
+            Err(KnnError::InvalidInput(_))
+        ));
+        crowded.fit(duplicates(63), None, false).unwrap();
+        assert!(matches!(
+            crowded.add(duplicates(1)[0], None),
+            Err(KnnError::InvalidInput(_))
+        ));
+        assert_eq!(crowded.len(), 63);
+    }
+
+    #[test]
+    fn class_bias_improves_macro_f1_on_an_imbalanced_validation_set() {
+        let points = |xs: &[f64], label: u8| -> Vec<Data<u8, 1>> {
+            xs.iter()
+                .map(|&x| Data {
+                    features: [x],
+                    label,
+                })
+                .collect()
+        };
+        // the rare class 1 sits inside the common class 0 and loses every
+        // unbiased vote
+        let mut train = points(&[0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0], 0);
+        train.extend(points(&[4.5, 5.5], 1));
+        let mut validation = points(&[0.2, 1.2, 2.2, 3.2, 7.8, 8.8], 0);
+        validation.extend(points(&[4.6, 5.4], 1));
+
+        let mut knn: Knn<Manhattan, u8, 1> = KnnBuilder::new()
+            .k(5)
+            .voting(VoteWeighting::Uniform)
+            .build()
+            .unwrap();
+        knn.fit(train, None, false).unwrap();
+
+        let macro_f1 = |knn: &Knn<Manhattan, u8, 1>| {
+            let actuals: Vec<u8> = validation
+                .iter()
+                .map(|data_point| data_point.label)
+                .collect();
+            let predictions: Vec<u8> = validation
+                .iter()
+                .map(|data_point| knn.predict(&data_point.features).unwrap())
+                .collect();
+
+            f1_macro(&actuals, &predictions)
+        };
+        let before = macro_f1(&knn);
+        knn.fit_class_bias(&validation);
+
+        assert!(macro_f1(&knn) > before, "{:?}", knn.class_bias());
+        assert_eq!(knn.predict(&[4.6]), Ok(1));
+    }
+
+    #[test]
+    fn lower_thresholds_recall_more_malignant_points() {
+        // a malignant strip inside benign points, outvoted at every query
+        let mut train: Vec<Data<Diagnosis, 1>> = (0..=10)
+            .map(|x| Data {
+                features: [f64::from(x)],
+                label: Diagnosis::Benign,
+            })
+            .collect();
+        train.extend([4.5, 5.5, 6.5].map(|x| Data {
+            features: [x],
+            label: Diagnosis::Malignant,
+        }));
+        let queries = [
+            ([5.0], Diagnosis::Malignant),
+            ([6.0], Diagnosis::Malignant),
+            ([0.5], Diagnosis::Benign),
+            ([9.5], Diagnosis::Benign),
+        ];
+
+        let mut knn: Knn<Manhattan, Diagnosis, 1> = KnnBuilder::new()
+            .k(5)
+            .voting(VoteWeighting::Uniform)
+            .build()
+            .unwrap();
+        knn.fit(train, None, false).unwrap();
+
+        let recall = |threshold: f64| {
+            let actuals: Vec<Diagnosis> = queries.iter().map(|&(_, label)| label).collect();
+            let predicted: Vec<Diagnosis> = queries
+                .iter()
+                .map(|(x, _)| knn.predict_with_threshold(x, threshold).unwrap())
+                .collect();
+
+            ConfusionMatrix::from_predictions(&actuals, &predicted).recall()
+        };
+
+        assert!(recall(0.5).abs() < f64::EPSILON);
+        assert!((recall(0.3) - 1.0).abs() < f64::EPSILON);
+        // 0.0 flags even a unanimously benign neighbourhood, 1.0 only a
+        // unanimously malignant one
+        assert_eq!(
+            knn.predict_with_threshold(&[0.5], 0.0),
+            Ok(Diagnosis::Malignant)
+        );
+        assert_eq!(
+            knn.predict_with_threshold(&[5.0], 1.0),
+            Ok(Diagnosis::Benign)
+        );
+    }
+
+    #[test]
+    fn kneighbors_lists_the_window_nearest_first_in_metric_units() {
+        let mut knn: Knn<kiddo::SquaredEuclidean, Diagnosis, 2> =
+            Knn::with_params(3, 0.0, &WindowType::Unfixed, kernel::Uniform).unwrap();
+        knn.fit(
+            labelled(&[
+                ([0.0, 0.0], Diagnosis::Benign),
+                ([3.0, 4.0], Diagnosis::Malignant),
+                ([0.0, 2.0], Diagnosis::Benign),
+                ([9.0, 9.0], Diagnosis::Malignant),
+                ([1.0, 0.0], Diagnosis::Malignant),
+            ]),
+            None,
+            false,
+        )
+        .unwrap();
+
+        let neighbours: Vec<(usize, f64, Diagnosis)> = knn
+            .kneighbors(&[0.0, 0.0])
+            .into_iter()
+            .map(|neighbour| (neighbour.index, neighbour.raw_distance, neighbour.label))
+            .collect();
+
+        // euclidean distances, not their squares
+        assert_eq!(
+            neighbours,
+            [
+                (0, 0.0, Diagnosis::Benign),
+                (4, 1.0, Diagnosis::Malignant),
+                (2, 2.0, Diagnosis::Benign),
+            ]
+        );
+
+        knn.set_radius(5.5).unwrap();
+        knn.set_window(WindowType::Fixed).unwrap();
+        assert_eq!(knn.kneighbors(&[0.0, 0.0]).len(), 4);
+        knn.set_radius(0.5).unwrap();
+        assert!(knn.kneighbors(&[5.0, -5.0]).is_empty());
+    }
+
+    #[test]
+    fn registered_metrics_replace_the_model_metric() {
+        let mut knn: Knn<Manhattan, Diagnosis, 2> =
+            Knn::with_params(1, 0.0, &WindowType::Unfixed, kernel::Uniform).unwrap();
+        knn.fit(
+            labelled(&[
+                ([0.0, 5.0], Diagnosis::Benign),
+                ([5.0, 0.0], Diagnosis::Malignant),
+            ]),
+            None,
+            false,
+        )
+        .unwrap();
+        let mut registry = MetricRegistry::with_builtins();
+        registry.register("second axis", |first, second| (first[1] - second[1]).abs());
+        let query = [0.0, 1.0];
+
+        assert_eq!(knn.predict(&query), Ok(Diagnosis::Benign));
+
+        knn.use_registered_metric(&registry, "second axis").unwrap();
+        assert_eq!(knn.predict(&query), Ok(Diagnosis::Malignant));
+        assert_eq!(knn.neighbors(&query), (vec![1.0], vec![1]));
+
+        assert!(matches!(
+            knn.use_registered_metric(&registry, "missing"),
+            Err(KnnError::InvalidInput(_))
+        ));
+        knn.clear_registered_metric();
+        assert_eq!(knn.predict(&query), Ok(Diagnosis::Benign));
+    }
+
+    #[test]
+    fn ranked_scores_put_the_likeliest_class_first_and_ties_in_label_order() {
+        let mut knn: Knn<Manhattan, u8, 1> = KnnBuilder::new()
+            .k(4)
+            .voting(VoteWeighting::Uniform)
+            .build()
+            .unwrap();
+        knn.fit(
+            [(0.0, 2), (0.1, 2), (1.0, 1), (-1.0, 0)]
+                .map(|(x, label)| Data {
+                    features: [x],
+                    label,
+                })
+                .to_vec(),
+            None,
+            false,
+        )
+        .unwrap();
+
+        let ranked = knn.ranked_scores(&[0.0]).unwrap();
+
+        assert_eq!(ranked, [(2, 0.5), (0, 0.25), (1, 0.25)]);
+        assert!((ranked.iter().map(|(_, score)| score).sum::<f64>() - 1.0).abs() < 1e-12);
+        assert_eq!(knn.predict(&[0.0]), Ok(ranked[0].0));
+    }
+
+    #[test]
+    fn nearest_ignores_the_window_and_reports_metric_distances() {
+        let mut knn: Knn<kiddo::SquaredEuclidean, Diagnosis, 2> =
+            Knn::with_params(1, 0.5, &WindowType::Fixed, kernel::Uniform).unwrap();
+        assert_eq!(knn.nearest(&[0.0, 0.0]), Err(KnnError::NotFitted));
+        knn.fit(
+            labelled(&[
+                ([0.0, 0.0], Diagnosis::Benign),
+                ([3.0, 4.0], Diagnosis::Malignant),
+                ([-3.0, -4.0], Diagnosis::Benign),
+            ]),
+            None,
+            false,
+        )
+        .unwrap();
+
+        // far outside the fixed window, at a euclidean distance of 1
+        assert_eq!(knn.nearest(&[3.0, 5.0]), Ok((1, 1.0, Diagnosis::Malignant)));
+        assert!(knn.predict(&[3.0, 5.0]).is_err());
+        assert!((knn.nearest_distance(&[0.0, 2.0]).unwrap() - 2.0).abs() < 1e-12);
+
+        // without the middle point the origin is 5 away from both outer
+        // ones, and either may win
+        knn.remove(0).unwrap();
+        let (index, distance, _) = knn.nearest(&[0.0, 0.0]).unwrap();
+        assert!(index == 1 || index == 2);
+        assert!((distance - 5.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn shuffling_the_deciding_feature_costs_more_than_shuffling_noise() {
+        use rand::Rng;
+
+        // the label follows the first feature, the second is narrow noise
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut points = |count: usize| -> Vec<Data<Diagnosis, 2>> {
+            (0..count)
+                .map(|_| {
+                    let x: f64 = rng.gen_range(0.0..10.0);
+                    Data {
+                        features: [x, rng.gen_range(0.0..0.5)],
+                        label: if x < 5.0 {
+                            Diagnosis::Benign
+                        } else {
+                            Diagnosis::Malignant
+                        },
+                    }
+                })
+                .collect()
+        };
+        let (train, test) = (points(200), points(200));
+        let mut knn: Knn<Manhattan, Diagnosis, 2> = KnnBuilder::new()
+            .k(5)
+            .voting(VoteWeighting::Uniform)
+            .build()
+            .unwrap();
+        knn.fit(train, None, false).unwrap();
+
+        let [deciding, noise] = knn.permutation_importance(&test, 0);
+
+        assert!(deciding > 0.3, "{deciding}");
+        assert!(noise.abs() < 0.05, "{noise}");
+        // the same seed shuffles the same way
+        assert_eq!(
+            knn.permutation_importance(&test, 0).map(f64::to_bits),
+            [deciding, noise].map(f64::to_bits)
+        );
+    }
+
+    #[test]
+    fn query_stats_count_the_points_each_search_examines() {
+        let grid: Vec<Data<Diagnosis, 2>> = (0..20)
+            .flat_map(|x| {
+                (0..20).map(move |y| Data {
+                    features: [f64::from(x), f64::from(y)],
+                    label: if x < 10 {
+                        Diagnosis::Benign
+                    } else {
+                        Diagnosis::Malignant
+                    },
+                })
+            })
+            .collect();
+        let mut tree: Knn<Manhattan, Diagnosis, 2> = KnnBuilder::new()
+            .k(3)
+            .kernel(kernel::Uniform)
+            .build()
+            .unwrap();
+        tree.fit(grid, None, false).unwrap();
+        tree.set_instrumented(true);
+        let mut brute = tree.clone();
+        brute.set_backend(Backend::BruteForce);
+
+        for query in [[2.5, 3.5], [15.5, 7.5], [12.5, 18.5]] {
+            assert_eq!(tree.predict(&query), brute.predict(&query));
+        }
+
+        // brute force measures the distance to every point once per query
+        assert_eq!(
+            brute.query_stats(),
+            QueryStats {
+                queries: 3,
+                nodes_visited: 0,
+                points_examined: 3 * 400,
+            }
+        );
+        assert!((brute.query_stats().mean_points_examined() - 400.0).abs() < 1e-12);
+
+        let stats = tree.query_stats();
+        assert_eq!(stats.queries, 3);
+        assert!(stats.nodes_visited > 0);
+        assert!(stats.points_examined >= 3 * 3);
+        assert!(stats.mean_points_examined() < 100.0, "{stats:?}");
+
+        tree.reset_query_stats();
+        assert_eq!(tree.query_stats(), QueryStats::default());
+    }
+
+    #[test]
+    fn normalized_weights_keep_their_ratios() {
+        let mut knn: Knn<Manhattan, Diagnosis, 2> =
+            Knn::with_params(3, 0.0, &WindowType::Unfixed, kernel::Epanechnikov).unwrap();
+        knn.fit(line(), Some(vec![1.0, 2.0, 4.0, 0.0, 3.0]), true)
+            .unwrap();
+
+        let mean = knn.weights.iter().sum::<f64>() / knn.weights.len() as f64;
+        assert!((mean - 1.0).abs() < 1e-12);
+        assert!((knn.weights[1] / knn.weights[0] - 2.0).abs() < 1e-12);
+        assert!((knn.weights[2] / knn.weights[4] - 4.0 / 3.0).abs() < 1e-12);
+        assert!(knn.weights[3] == 0.0);
+    }
+
+    #[test]
+    fn infinite_kernel_outputs_are_checked() {
+        fn spike(distance: f64) -> f64 {
+            if distance == 0.0 {
+                f64::INFINITY
+            } else {
+                1.0
+            }
+        }
+
+        let mut knn: Knn<Manhattan, Diagnosis, 2> =
+            Knn::with_params(3, 0.0, &WindowType::Unfixed, spike).unwrap();
+        knn.fit(line(), None, false).unwrap();
+        let on_malignant = [10.0, 0.0];
+
+        knn.set_kernel_check(KernelCheck::Strict);
+        assert!(matches!(
+            knn.predict(&on_malignant),
+            Err(KnnError::InvalidInput(_))
+        ));
+
+        knn.set_kernel_check(KernelCheck::Lenient);
+        let probabilities = knn.predict_proba(&on_malignant).unwrap();
+        assert!(probabilities
+            .values()
+            .all(|probability| probability.is_finite()));
+        assert_eq!(knn.warnings(), 1);
+    }
+
+    /// One malignant point right next to the query against two benign ones
+    /// further out
+    fn close_minority() -> Vec<Data<Diagnosis, 2>> {
+        labelled(&[
+            ([0.1, 0.0], Diagnosis::Malignant),
+            ([1.0, 0.0], Diagnosis::Benign),
+            ([1.2, 0.0], Diagnosis::Benign),
+            ([9.0, 0.0], Diagnosis::Benign),
+        ])
+    }
+
+    #[test]
+    fn inverse_distance_outvotes_the_majority() {
+        let query = [0.0, 0.0];
+        let vote = |voting| {
+            let mut knn: Knn<Manhattan, Diagnosis, 2> = KnnBuilder::new()
+                .k(3)
+                .kernel(kernel::Uniform)
+                .voting(voting)
+                .build()
+                .unwrap();
+            knn.fit(close_minority(), None, false).unwrap();
+
+            knn.predict(&query).unwrap()
+        };
+
+        assert_eq!(vote(VoteWeighting::Uniform), Diagnosis::Benign);
+        assert_eq!(
+            vote(VoteWeighting::InverseDistance { epsilon: 1e-9 }),
+            Diagnosis::Malignant
+        );
+    }
+
+    #[test]
+    fn inverse_distance_keeps_exact_matches_finite() {
+        let mut knn: Knn<Manhattan, Diagnosis, 2> = KnnBuilder::new()
+            .k(3)
+            .voting(VoteWeighting::InverseDistance { epsilon: 1e-9 })
+            .build()
+            .unwrap();
+        knn.fit(close_minority(), None, false).unwrap();
+
+        let on_malignant = [0.1, 0.0];
+        assert_eq!(knn.predict(&on_malignant), Ok(Diagnosis::Malignant));
+        assert!(knn
+            .predict_proba(&on_malignant)
+            .unwrap()
+            .values()
+            .all(|probability| probability.is_finite()));
+
+        for epsilon in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let voting = VoteWeighting::InverseDistance { epsilon };
+
+            assert!(knn.set_vote_weighting(voting).is_err());
+            assert!(KnnBuilder::new()
+                .voting(voting)
+                .build::<Manhattan, Diagnosis, 2, f64>()
+                .is_err());
+        }
+        assert_eq!(
+            knn.vote_weighting(),
+            VoteWeighting::InverseDistance { epsilon: 1e-9 }
+        );
+    }
+
+    #[test]
+    fn set_k_predicts_like_a_fresh_model() {
+        let queries = [[0.5, 0.0], [3.0, 1.0], [6.0, 0.0], [10.5, 0.0]];
+        let data = line();
+
+        let mut reused: Knn<Manhattan, Diagnosis, 2> =
+            Knn::with_params(1, 0.0, &WindowType::Unfixed, kernel::Triangular).unwrap();
+        reused.fit(data.clone(), None, false).unwrap();
+        reused.set_k(4).unwrap();
+
+        let mut fresh: Knn<Manhattan, Diagnosis, 2> =
+            Knn::with_params(4, 0.0, &WindowType::Unfixed, kernel::Triangular).unwrap();
+        fresh.fit(data, None, false).unwrap();
+
+        for query in &queries {
+            assert_eq!(reused.predict_proba(query), fresh.predict_proba(query));
+        }
+    }
+
+    #[test]
+    fn setters_reject_windows_without_their_parameter() {
+        let mut knn: Knn<Manhattan, Diagnosis, 2> =
+            Knn::with_params(3, 0.0, &WindowType::Unfixed, kernel::Uniform).unwrap();
+        knn.fit(line(), None, false).unwrap();
+
+        assert!(knn.set_k(0).is_err());
+        assert!(knn
+            .set_window(WindowType::Capped {
+                radius: 1.0,
+                max_k: 0
+            })
+            .is_err());
+        assert!(knn.set_window(WindowType::Adaptive { m: 0 }).is_err());
+        assert!(knn.set_window(WindowType::Fixed).is_err());
+        for radius in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(matches!(
+                knn.set_radius(radius),
+                Err(KnnError::InvalidInput(_))
+            ));
+        }
+
+        assert_eq!(knn.k(), 3);
+        assert!(knn.radius().abs() < f64::EPSILON);
+        assert!(matches!(knn.window(), WindowType::Unfixed));
+        assert!(knn.predict(&[0.0, 0.0]).is_ok());
+
+        knn.set_radius(2.5).unwrap();
+        knn.set_window(WindowType::Fixed).unwrap();
+        assert_eq!(knn.predict(&[0.0, 0.0]), Ok(Diagnosis::Benign));
+    }
+
+    #[test]
+    fn k_above_the_point_count_uses_every_point() {
+        let mut knn: Knn<Manhattan, Diagnosis, 2> =
+            Knn::with_params(50, 0.0, &WindowType::Unfixed, kernel::Uniform).unwrap();
+        knn.fit(line(), None, false).unwrap();
+
+        assert_eq!(knn.predict_with_neighbors(&[0.0, 0.0]).unwrap().len(), 5);
+        assert_eq!(knn.predict(&[0.0, 0.0]), Ok(Diagnosis::Benign));
+    }
+
+    #[test]
+    fn removing_the_only_close_point_changes_the_prediction() {
+        let mut knn: Knn<Manhattan, Diagnosis, 2> =
+            Knn::with_params(1, 0.0, &WindowType::Unfixed, kernel::Uniform).unwrap();
+        knn.fit(line(), None, false).unwrap();
+        let next_to_malignant = [9.0, 0.0];
+
+        assert_eq!(knn.predict(&next_to_malignant), Ok(Diagnosis::Malignant));
+        knn.remove(3).unwrap();
+        knn.remove(4).unwrap();
+
+        assert_eq!(knn.predict(&next_to_malignant), Ok(Diagnosis::Benign));
+        assert_eq!(knn.len(), 3);
+        assert_eq!(knn.classes(), [Diagnosis::Benign]);
+        assert_eq!(knn.remove(3), Err(KnnError::UnknownPoint(3)));
+        assert_eq!(knn.remove(5), Err(KnnError::UnknownPoint(5)));
+
+        knn.add(
+            Data {
+                features: [12.0, 0.0],
+                label: Diagnosis::Malignant,
+            },
+            None,
+        )
+        .unwrap();
+        assert_eq!(knn.len(), 4);
+        assert_eq!(knn.predict(&next_to_malignant), Ok(Diagnosis::Malignant));
+    }
+
+    #[test]
+    fn iteration_skips_removed_points() {
+        let mut knn: Knn<Manhattan, Diagnosis, 2> =
+            Knn::with_params(1, 0.0, &WindowType::Unfixed, kernel::Uniform).unwrap();
+        knn.fit(line(), None, false).unwrap();
+        assert_eq!((&knn).into_iter().count(), 5);
+
+        knn.remove(1).unwrap();
+
+        let mut count = 0;
+        for data_point in &knn {
+            assert!((data_point.features[0] - 1.0).abs() > f64::EPSILON);
+            count += 1;
+        }
+        assert_eq!(count, knn.len());
+        assert_eq!(knn.iter().count(), 4);
+    }
+
+    #[test]
+    fn pruning_drops_light_points_and_keeps_predicting() {
+        let mut data = line();
+        data.push(Data {
+            features: [1.5, 0.0],
+            label: Diagnosis::Malignant,
+        });
+        let mut knn: Knn<Manhattan, Diagnosis, 2> =
+            Knn::with_params(1, 0.0, &WindowType::Unfixed, kernel::Uniform).unwrap();
+        knn.fit(data, Some(vec![1.0, 1.0, 1.0, 1.0, 1.0, 0.1]), false)
+            .unwrap();
+        assert_eq!(knn.predict(&[1.5, 0.0]), Ok(Diagnosis::Malignant));
+
+        knn.prune_by_weight(0.5);
+
+        assert_eq!(knn.len(), 5);
+        assert!(knn.weights.iter().all(|&weight| weight >= 0.5));
+        assert_eq!(knn.predict(&[1.5, 0.0]), Ok(Diagnosis::Benign));
+        assert_eq!(knn.predict(&[10.4, 0.0]), Ok(Diagnosis::Malignant));
+        assert!(knn
+            .kneighbors(&[1.5, 0.0])
+            .iter()
+            .all(|neighbour| neighbour.index < knn.len()));
+    }
+
+    #[test]
+    fn provenance_records_the_voting_neighbours() {
+        let mut knn: Knn<Manhattan, Diagnosis, 2> =
+            Knn::with_params(3, 0.0, &WindowType::Unfixed, kernel::Epanechnikov).unwrap();
+        knn.fit(line(), Some(vec![1.0, 2.0, 1.0, 1.0, 1.0]), false)
+            .unwrap();
+
+        for query in [[0.5, 0.0], [9.0, 1.0]] {
+            let (prediction, provenance) = knn.predict_with_provenance(&query).unwrap();
+            let (_, indices) = knn.neighbors(&query);
+
+            assert_eq!(Ok(prediction), knn.predict(&query));
+            assert_eq!(provenance.neighbors, indices);
+            assert_eq!(
+                provenance.weights,
+                indices
+                    .iter()
+                    .map(|&index| knn.weights[index])
+                    .collect::<Vec<_>>()
+            );
+            assert_eq!(
+                provenance.kernel_weights,
+                knn.predict_with_neighbors(&query)
+                    .unwrap()
+                    .iter()
+                    .map(|neighbour| neighbour.kernel_weight)
+                    .collect::<Vec<_>>()
+            );
+            assert_eq!(provenance.kernel, "epanechnikov");
+            assert_eq!(provenance.metric, "manhattan");
+            assert!(matches!(provenance.window, WindowType::Unfixed));
+        }
+
+        knn.set_kernel(kernel::epanechnikov);
+        let (_, provenance) = knn.predict_with_provenance(&[0.5, 0.0]).unwrap();
+        assert_eq!(provenance.kernel, "custom");
+    }
+
+    #[test]
+    fn empty_fixed_windows_are_their_own_error() {
+        let mut knn: Knn<Manhattan, Diagnosis, 2> =
+            Knn::with_params(1, 1.5, &WindowType::Fixed, kernel::Uniform).unwrap();
+        assert_eq!(
+            knn.predict_fixed(&[0.0, 0.0]),
+            Err(WindowError::Prediction(KnnError::NotFitted))
+        );
+
+        knn.fit(line(), None, false).unwrap();
+        assert_eq!(knn.predict_fixed(&[0.5, 0.0]), Ok(Diagnosis::Benign));
+        assert_eq!(knn.predict_fixed(&[6.0, 0.0]), Err(WindowError::Empty));
+
+        knn.set_window(WindowType::Unfixed).unwrap();
+        assert!(matches!(
+            knn.predict_fixed(&[6.0, 0.0]),
+            Err(WindowError::Prediction(KnnError::InvalidInput(_)))
+        ));
+    }
+
+    #[test]
+    fn labels_other_than_diagnosis_predict() {
+        use crate::parse::phones::PhoneOs;
+
+        let data = vec![
+            Data {
+                features: [400.0, 6.5],
+                label: PhoneOs::Android,
+            },
+            Data {
+                features: [380.0, 6.0],
+                label: PhoneOs::Android,
+            },
+            Data {
+                features: [100.0, 2.0],
+                label: PhoneOs::IOs,
+            },
+            Data {
+                features: [90.0, 1.5],
+                label: PhoneOs::IOs,
+            },
+        ];
+        let mut knn: Knn<Manhattan, PhoneOs, 2> =
+            Knn::with_params(1, 0.0, &WindowType::Unfixed, kernel::Uniform).unwrap();
+        knn.fit(data, None, false).unwrap();
+
+        assert_eq!(knn.predict(&[390.0, 6.2]), Ok(PhoneOs::Android));
+        assert_eq!(knn.predict(&[95.0, 1.8]), Ok(PhoneOs::IOs));
+    }
+
+    /// Six numeric phone features: app usage, screen time, battery drain,
+    /// apps installed, data usage and age
+    #[test]
+    fn six_dimensional_phone_data_predicts() {
+        use crate::parse::phones::PhoneOs;
+
+        let data = vec![
+            Data {
+                features: [393.0, 6.4, 1872.0, 67.0, 1122.0, 40.0],
+                label: PhoneOs::Android,
+            },
+            Data {
+                features: [410.0, 6.9, 1950.0, 70.0, 1180.0, 38.0],
+                label: PhoneOs::Android,
+            },
+            Data {
+                features: [95.0, 1.9, 600.0, 15.0, 300.0, 25.0],
+                label: PhoneOs::IOs,
+            },
+            Data {
+                features: [110.0, 2.2, 650.0, 18.0, 320.0, 27.0],
+                label: PhoneOs::IOs,
+            },
+        ];
+        let mut knn: Knn<Manhattan, PhoneOs, 6> =
+            Knn::with_params(1, 0.0, &WindowType::Unfixed, kernel::Uniform).unwrap();
+        knn.fit(data, None, false).unwrap();
+
+        assert_eq!(
+            knn.predict(&[400.0, 6.6, 1900.0, 68.0, 1150.0, 39.0]),
+            Ok(PhoneOs::Android)
+        );
+        assert_eq!(
+            knn.predict(&[100.0, 2.0, 620.0, 16.0, 310.0, 24.0]),
+            Ok(PhoneOs::IOs)
+        );
+    }
+
+    #[test]
+    fn predicting_before_fit_is_an_error() {
+        let knn: Knn<Manhattan, Diagnosis, 2> =
+            Knn::with_params(3, 0.0, &WindowType::Unfixed, kernel::Uniform).unwrap();
+
+        assert_eq!(knn.predict(&[0.0, 0.0]), Err(KnnError::NotFitted));
+        assert_eq!(knn.predict_proba(&[0.0, 0.0]), Err(KnnError::NotFitted));
+    }
+
+    #[test]
+    fn capped_windows_keep_the_nearest_points_inside_the_radius() {
+        let window = WindowType::Capped {
+            radius: 1.5,
+            max_k: 5,
+        };
+        let mut unfixed: Knn<Manhattan, Diagnosis, 2> =
+            Knn::with_params(5, 0.0, &WindowType::Unfixed, kernel::Uniform).unwrap();
+        unfixed.fit(line(), None, false).unwrap();
+        let mut capped = unfixed.clone();
+        capped.set_window(window).unwrap();
+
+        let neighbours = capped.kneighbors(&[0.0, 0.0]);
+
+        assert_eq!(unfixed.kneighbors(&[0.0, 0.0]).len(), 5);
+        assert_eq!(
+            neighbours
+                .iter()
+                .map(|neighbour| (neighbour.index, neighbour.normalized_distance))
+                .collect::<Vec<_>>(),
+            [(0, 0.0), (1, 1.0 / 1.5)]
+        );
+        assert_eq!(capped.predict(&[0.0, 0.0]), Ok(Diagnosis::Benign));
+
+        capped
+            .set_window(WindowType::Capped {
+                radius: 1.5,
+                max_k: 1,
+            })
+            .unwrap();
+        assert_eq!(capped.kneighbors(&[0.5, 0.0]).len(), 1);
+
+        assert_eq!(
+            capped.predict(&[6.0, 0.0]),
+            Err(KnnError::NoNeighborsInRadius { radius: 1.5 })
+        );
+    }
+
+    #[test]
+    fn adaptive_windows_give_sparse_queries_m_contributing_neighbours() {
+        let mut adaptive: Knn<Manhattan, Diagnosis, 2> = KnnBuilder::new()
+            .window(WindowType::Adaptive { m: 3 })
+            .kernel(kernel::Epanechnikov)
+            .build()
+            .unwrap();
+        adaptive.fit(line(), None, false).unwrap();
+        let mut fixed = adaptive.clone();
+        fixed.set_radius(1.5).unwrap();
+        fixed.set_window(WindowType::Fixed).unwrap();
+
+        for query in [[50.0, 0.0], [-30.0, 5.0], [1.0, 0.0]] {
+            let neighbours = adaptive.kneighbors(&query);
+
+            assert_eq!(neighbours.len(), 3, "{query:?}");
+            assert!(neighbours
+                .iter()
+                .all(|neighbour| neighbour.kernel_weight > 0.0));
+        }
+        // the one global radius leaves the far queries without any
+        assert!(fixed.kneighbors(&[50.0, 0.0]).is_empty());
+        assert_eq!(adaptive.predict(&[50.0, 0.0]), Ok(Diagnosis::Malignant));
+    }
+
+    #[test]
+    fn with_params_rejects_windows_without_their_parameter() {
+        for (k, window) in [
+            (0, WindowType::Unfixed),
+            (
+                3,
+                WindowType::Capped {
+                    radius: 1.0,
+                    max_k: 0,
+                },
+            ),
+            (3, WindowType::Adaptive { m: 0 }),
+        ] {
+            assert!(
+                Knn::<Manhattan, Diagnosis, 2>::with_params(k, 1.0, &window, kernel::Uniform)
+                    .is_err()
+            );
+        }
+    }
+
+    /// Indices of the neighbours a fixed window finds around every query
+    /// against the points within `radius` in the metric's own units
+    fn fixed_window_against_brute_force<M: DistanceMetric<f64, 2> + MetricKind>(radius: f64) {
+        let data: Vec<Data<Diagnosis, 2>> = (0..=10)
+            .flat_map(|x| [(f64::from(x), 0.0), (f64::from(x), 1.5)])
+            .map(|(x, y)| Data {
+                features: [x, y],
+                label: if x < 4.0 {
+                    Diagnosis::Benign
+                } else {
+                    Diagnosis::Malignant
+                },
+            })
+            .collect();
+        let mut knn: Knn<M, Diagnosis, 2> =
+            Knn::with_params(1, radius, &WindowType::Fixed, kernel::Uniform).unwrap();
+        knn.fit(data.clone(), None, false).unwrap();
+
+        for query in [[0.25, 0.1], [4.6, 0.8], [9.9, 1.2], [30.0, 30.0]] {
+            let (distances, mut found) = knn.neighbors(&query);
+            found.sort_unstable();
+            let expected: Vec<usize> = (0..data.len())
+                .filter(|&index| M::finalize(M::dist(&query, &data[index].features)) <= radius)
+                .collect();
+
+            assert!(distances.iter().all(|&distance| distance <= radius));
+            assert_eq!(found, expected, "{query:?}");
+            if expected.is_empty() {
+                assert_eq!(
+                    knn.predict(&query),
+                    Err(KnnError::NoNeighborsInRadius { radius })
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn fixed_windows_hold_exactly_the_points_within_the_radius() {
+        fixed_window_against_brute_force::<Manhattan>(3.25);
+        fixed_window_against_brute_force::<kiddo::SquaredEuclidean>(2.2);
+        fixed_window_against_brute_force::<crate::distance_metric::Chebyshev>(1.3);
+    }
+
+    #[test]
+    fn tied_predictions_repeat_exactly() {
+        let mut knn: Knn<Manhattan, u8, 2> =
+            Knn::with_params(3, 0.0, &WindowType::Unfixed, kernel::Uniform).unwrap();
+        knn.fit(
+            [([-1.0, 0.0], 2), ([1.0, 0.0], 0), ([0.0, 1.0], 1)]
+                .map(|(features, label)| Data { features, label })
+                .to_vec(),
+            None,
+            false,
+        )
+        .unwrap();
+
+        // all three classes tie at the origin
+        let query = [0.0, 0.0];
+        let first = (knn.predict(&query), knn.predict_proba(&query));
+        for _ in 0..100 {
+            assert_eq!((knn.predict(&query), knn.predict_proba(&query)), first);
+        }
+        assert_eq!(first.1.unwrap().len(), 3);
+    }
+
+    #[test]
+    fn exact_ties_follow_the_tie_break_policy() {
+        // one vote each, the benign point nearer
+        let mut knn: Knn<Manhattan, Diagnosis, 2> = KnnBuilder::new()
+            .k(2)
+            .voting(VoteWeighting::Uniform)
+            .build()
+            .unwrap();
+        knn.fit(
+            labelled(&[
+                ([-1.0, 0.0], Diagnosis::Benign),
+                ([1.5, 0.0], Diagnosis::Malignant),
+            ]),
+            None,
+            false,
+        )
+        .unwrap();
+        let query = [0.0, 0.0];
+
+        assert_eq!(knn.tie_break(), TieBreak::PreferNearest);
+        assert_eq!(knn.predict(&query), Ok(Diagnosis::Benign));
+
+        knn.set_tie_break(TieBreak::PreferLabel(Diagnosis::Malignant));
+        assert_eq!(knn.predict(&query), Ok(Diagnosis::Malignant));
+
+        knn.set_tie_break(TieBreak::Error);
+        assert_eq!(knn.predict(&query), Err(KnnError::Tie));
+        // a single vote needs no tie break
+        knn.set_k(1).unwrap();
+        assert_eq!(knn.predict(&query), Ok(Diagnosis::Benign));
+    }
+
+    #[test]
+    fn nan_scores_lose_instead_of_panicking() {
+        // the three benign points get NaN votes, the far malignant one 0.5
+        let nan_near = |distance: f64| {
+            if distance < 0.5 {
+                f64::NAN
+            } else {
+                1.0 - distance / 2.0
+            }
+        };
+        let mut knn: Knn<Manhattan, Diagnosis, 2> =
+            Knn::with_params(4, 0.0, &WindowType::Unfixed, nan_near).unwrap();
+        knn.set_kernel_check(KernelCheck::Off);
+        knn.fit(line(), None, false).unwrap();
+
+        assert_eq!(knn.predict(&[3.0, 0.0]), Ok(Diagnosis::Malignant));
+        let probabilities = knn.predict_proba(&[3.0, 0.0]).unwrap();
+        assert!(probabilities[&Diagnosis::Benign].abs() < f64::EPSILON);
+        assert!((probabilities[&Diagnosis::Malignant] - 1.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn fitting_twice_keeps_only_the_second_fit() {
+        let build = || -> Knn<Manhattan, Diagnosis, 2> {
+            Knn::with_params(5, 0.0, &WindowType::Unfixed, kernel::Epanechnikov).unwrap()
+        };
+        let weights = vec![1.0, 0.5, 2.0, 1.0, 0.1];
+
+        let mut refitted = build();
+        refitted.fit(line(), None, false).unwrap();
+        refitted.fit(line(), Some(weights.clone()), false).unwrap();
+        let mut fresh = build();
+        fresh.fit(line(), Some(weights), false).unwrap();
+
+        assert_eq!(refitted.len(), 5);
+        for step in 0..=24 {
+            let query = [f64::from(step) * 0.5, 0.0];
+            let mut indices: Vec<usize> = refitted
+                .kneighbors(&query)
+                .iter()
+                .map(|neighbour| neighbour.index)
+                .collect();
+            indices.sort_unstable();
+            indices.dedup();
+
+            assert_eq!(indices.len(), 5);
+            assert_eq!(refitted.predict_proba(&query), fresh.predict_proba(&query));
+        }
+    }
+
+    #[test]
+    fn adding_points_one_at_a_time_predicts_like_one_fit() {
+        let data = line();
+        let weights = [1.0, 2.0, 0.5, 1.0, 3.0];
+        let build = || -> Knn<Manhattan, Diagnosis, 2> {
+            Knn::with_params(3, 0.0, &WindowType::Unfixed, kernel::Epanechnikov).unwrap()
+        };
+
+        let mut fitted = build();
+        fitted
+            .fit(data.clone(), Some(weights.to_vec()), false)
+            .unwrap();
+        let mut added = build();
+        for (data_point, weight) in data.into_iter().zip(weights) {
+            added.add(data_point, Some(weight)).unwrap();
+        }
+
+        for step in 0..=24 {
+            let query = [f64::from(step) * 0.5, 0.3];
+            assert_eq!(added.predict(&query), fitted.predict(&query));
+            assert_eq!(added.predict_proba(&query), fitted.predict_proba(&query));
+        }
+    }
+
+    #[test]
+    fn manhattan_fixed_windows_use_the_radius_unsquared() {
+        // clear of every point distance, so the window edge is not under test
+        const RADIUS: f64 = 3.25;
+        let data: Vec<Data<Diagnosis, 2>> = (0..=10)
+            .flat_map(|x| [(f64::from(x), 0.0), (f64::from(x), 1.5)])
+            .map(|(x, y)| Data {
+                features: [x, y],
+                label: if x < 4.0 {
+                    Diagnosis::Benign
+                } else {
+                    Diagnosis::Malignant
+                },
+            })
+            .collect();
+        let mut knn: Knn<Manhattan, Diagnosis, 2> =
+            Knn::with_params(1, RADIUS, &WindowType::Fixed, kernel::Uniform).unwrap();
+        knn.fit(data.clone(), None, false).unwrap();
+
+        let query = [0.0, 0.0];
+        let mut found: Vec<usize> = knn
+            .predict_with_neighbors(&query)
+            .unwrap()
+            .iter()
+            .map(|neighbour| neighbour.index)
+            .collect();
+        found.sort_unstable();
+        let expected: Vec<usize> = (0..data.len())
+            .filter(|&index| {
+                let [x, y] = data[index].features;
+                x.abs() + y.abs() <= RADIUS
+            })
+            .collect();
+
+        assert_eq!(found, expected);
+        // within the squared radius the malignant points would outvote them
+        assert_eq!(knn.predict(&query), Ok(Diagnosis::Benign));
+    }
+
+    #[test]
+    fn batches_of_nothing_predict_nothing() {
+        let mut knn: Knn<Manhattan, Diagnosis, 2> =
+            Knn::with_params(3, 0.0, &WindowType::Unfixed, kernel::Uniform).unwrap();
+        knn.fit(line(), None, false).unwrap();
+
+        assert!(knn.predict_batch(&[]).is_empty());
+    }
+
+    #[test]
+    fn batches_predict_row_by_row_in_order() {
+        let mut knn: Knn<Manhattan, Diagnosis, 2> =
+            Knn::with_params(1, 1.5, &WindowType::Fixed, kernel::Uniform).unwrap();
+        knn.fit(line(), None, false).unwrap();
+
+        // the middle rows are farther than the radius from every point
+        let queries = [[0.5, 0.0], [5.0, 0.0], [10.5, 0.0], [6.0, 3.0], [1.5, 0.5]];
+        let predictions = knn.predict_batch(&queries);
+
+        assert_eq!(predictions.len(), queries.len());
+        for (prediction, query) in predictions.iter().zip(&queries) {
+            assert_eq!(*prediction, knn.predict(query));
+        }
+        assert_eq!(predictions[0], Ok(Diagnosis::Benign));
+        assert_eq!(
+            predictions[1],
+            Err(KnnError::NoNeighborsInRadius { radius: 1.5 })
+        );
+        assert_eq!(predictions[2], Ok(Diagnosis::Malignant));
+        assert!(predictions[3].is_err());
+        assert_eq!(predictions[4], Ok(Diagnosis::Benign));
+    }
+
+    #[test]
+    fn negative_and_nan_kernels_fail_strict_and_warn_lenient() {
+        for bad_kernel in [(|_| -1.0) as fn(f64) -> f64, |_| f64::NAN] {
+            let mut knn: Knn<Manhattan, Diagnosis, 2> =
+                Knn::with_params(3, 0.0, &WindowType::Unfixed, bad_kernel).unwrap();
+            knn.fit(line(), None, false).unwrap();
+
+            knn.set_kernel_check(KernelCheck::Strict);
+            assert!(matches!(
+                knn.predict_proba(&[0.5, 0.0]),
+                Err(KnnError::InvalidInput(_))
+            ));
+            assert_eq!(knn.warnings(), 0);
+
+            knn.set_kernel_check(KernelCheck::Lenient);
+            assert!(knn.predict_proba(&[0.5, 0.0]).is_ok());
+            assert_eq!(knn.warnings(), 3);
+        }
+    }
+
+    #[test]
+    fn smoothing_keeps_unanimous_probabilities_inside_zero_and_one() {
+        let mut knn: Knn<Manhattan, Diagnosis, 2> =
+            Knn::with_params(3, 0.0, &WindowType::Unfixed, kernel::Epanechnikov).unwrap();
+        knn.fit(line(), None, false).unwrap();
+        let all_benign = [0.5, 0.0];
+
+        let unsmoothed = knn.predict_proba_dense(&all_benign).unwrap();
+        assert!(unsmoothed.contains(&0.0));
+
+        knn.set_smoothing(1.0);
+        let smoothed = knn.predict_proba_dense(&all_benign).unwrap();
+        assert!(smoothed
+            .iter()
+            .all(|&probability| probability > 0.0 && probability < 1.0));
+        assert_eq!(knn.predict(&all_benign), Ok(Diagnosis::Benign));
+    }
+
+    fn fit_with_weights(weights: Vec<f64>) -> Result<(), KnnError> {
+        let mut knn: Knn<Manhattan, Diagnosis, 2> =
+            Knn::with_params(3, 0.0, &WindowType::Unfixed, kernel::Uniform).unwrap();
+        knn.fit(line(), Some(weights), false)
+    }
+
+    #[test]
+    fn too_few_weights_are_rejected() {
+        assert_eq!(
+            fit_with_weights(vec![1.0; 4]),
+            Err(KnnError::WeightLengthMismatch {
+                expected: 5,
+                got: 4
+            })
+        );
+    }
+
+    #[test]
+    fn too_many_weights_are_rejected() {
+        assert_eq!(
+            fit_with_weights(vec![1.0; 6]),
+            Err(KnnError::WeightLengthMismatch {
+                expected: 5,
+                got: 6
+            })
+        );
+    }
+
+    #[test]
+    fn non_finite_features_are_rejected_by_position() {
+        let mut knn: Knn<Manhattan, Diagnosis, 2> =
+            Knn::with_params(3, 0.0, &WindowType::Unfixed, kernel::Uniform).unwrap();
+
+        let mut poisoned = line();
+        poisoned[2].features[1] = f64::NAN;
+        assert_eq!(
+            knn.fit(poisoned, None, false),
+            Err(KnnError::InvalidInput(
+                "feature 1 of point 2 is NaN".to_string()
+            ))
+        );
+
+        knn.fit(line(), None, false).unwrap();
+        assert_eq!(knn.predict(&[0.5, 0.0]), Ok(Diagnosis::Benign));
+        assert_eq!(
+            knn.predict(&[f64::NAN, 0.0]),
+            Err(KnnError::InvalidInput(
+                "feature 0 of the query is NaN".to_string()
+            ))
+        );
+        assert!(matches!(
+            knn.predict_proba(&[0.0, f64::NEG_INFINITY]),
+            Err(KnnError::InvalidInput(_))
+        ));
+        assert!(matches!(
+            knn.add(
+                Data {
+                    features: [f64::INFINITY, 0.0],
+                    label: Diagnosis::Benign,
+                },
+                None
+            ),
+            Err(KnnError::InvalidInput(_))
+        ));
+        assert_eq!(knn.len(), 5);
+    }
+
+    #[test]
+    fn nan_weights_are_rejected() {
+        assert!(matches!(
+            fit_with_weights(vec![1.0, 1.0, f64::NAN, 1.0, 1.0]),
+            Err(KnnError::InvalidWeight { index: 2, weight }) if weight.is_nan()
+        ));
+    }
+
+    #[test]
+    fn infinite_weights_are_rejected() {
+        assert!(matches!(
+            fit_with_weights(vec![1.0, f64::INFINITY, 1.0, 1.0, 1.0]),
+            Err(KnnError::InvalidWeight { index: 1, weight }) if weight.is_infinite()
+        ));
+    }
+
+    #[test]
+    fn negative_weights_are_rejected() {
+        assert!(matches!(
+            fit_with_weights(vec![1.0, 1.0, 1.0, 1.0, -0.5]),
+            Err(KnnError::InvalidWeight { index: 4, weight }) if weight < 0.0
+        ));
+    }
+
+    #[test]
+    fn f32_models_predict_like_f64_models() {
+        let mut wide: Knn<Manhattan, Diagnosis, 2> =
+            Knn::with_params(3, 0.0, &WindowType::Unfixed, kernel::Epanechnikov).unwrap();
+        wide.fit(line(), None, false).unwrap();
+
+        let mut narrow: Knn<Manhattan, Diagnosis, 2, f32> =
+            Knn::with_params(3, 0.0, &WindowType::Unfixed, kernel::Epanechnikov).unwrap();
+        narrow
+            .fit(
+                line()
+                    .into_iter()
+                    .map(|data_point| Data {
+                        features: data_point.features.map(f32::from_f64),
+                        label: data_point.label,
+                    })
+                    .collect(),
+                None,
+                false,
+            )
+            .unwrap();
+
+        for step in 0..=24_u8 {
+            let query = [f64::from(step) * 0.5, 0.25];
+            assert_eq!(
+                narrow.predict(&query.map(f32::from_f64)),
+                wide.predict(&query)
+            );
+        }
+    }
+
+    #[test]
+    fn probabilities_sum_to_one_and_peak_at_the_prediction() {
+        let mut knn: Knn<Manhattan, Diagnosis, 2> =
+            Knn::with_params(4, 0.0, &WindowType::Unfixed, kernel::Epanechnikov).unwrap();
+        knn.fit(line(), None, false).unwrap();
+
+        // offset so no query is equidistant from both classes, ties are up to
+        // the tie-break rule
+        for step in 0..=24_u8 {
+            let query = [f64::from(step) * 0.5 + 0.1, 0.5];
+            let probabilities = knn.predict_proba(&query).unwrap();
+
+            assert!((probabilities.values().sum::<f64>() - 1.0).abs() < 1e-12);
+            let (most_probable, _) = probabilities
+                .iter()
+                .max_by(|first, second| first.1.total_cmp(second.1))
+                .unwrap();
+            assert_eq!(knn.predict(&query), Ok(*most_probable));
+        }
+    }
+
+    #[test]
+    fn zero_kernel_weights_give_uniform_probabilities_over_the_neighbours() {
+        let mut knn: Knn<Manhattan, Diagnosis, 2> =
+            Knn::with_params(2, 0.0, &WindowType::Unfixed, |_| 0.0).unwrap();
+        knn.fit(line(), None, false).unwrap();
+
+        // the two nearest neighbours are one benign and one malignant point
+        let probabilities = knn.predict_proba(&[6.0, 0.0]).unwrap();
+
+        assert_eq!(probabilities.len(), 2);
+        assert!(probabilities
+            .values()
+            .all(|probability| (probability - 0.5).abs() < 1e-12));
+    }
+
+    #[test]
+    fn kernel_weights_divide_by_the_window_bandwidth() {
+        let weights = |distances: &[f64], window, bandwidth| {
+            kernel_weights(distances, window, 2.0, bandwidth, &kernel::Triangular)
+        };
+
+        // fixed and capped windows divide by the radius, unfixed ones by the
+        // farthest or median distance, adaptive ones by the bandwidth point
+        assert_eq!(
+            weights(&[0.5, 1.0], WindowType::Fixed, Bandwidth::MaxNeighbor),
+            [0.75, 0.5]
+        );
+        assert_eq!(
+            weights(
+                &[0.5, 1.0],
+                WindowType::Capped {
+                    radius: 4.0,
+                    max_k: 2
+                },
+                Bandwidth::MaxNeighbor
+            ),
+            [0.875, 0.75]
+        );
+        assert_eq!(
+            weights(&[1.0, 2.0], WindowType::Unfixed, Bandwidth::MaxNeighbor),
+            [0.5, 0.0]
+        );
+        assert_eq!(
+            weights(
+                &[1.0, 2.0, 4.0],
+                WindowType::Unfixed,
+                Bandwidth::MedianNeighbor
+            ),
+            [0.5, 0.0, 0.0]
+        );
+        assert_eq!(
+            weights(
+                &[1.0, 2.0, 4.0],
+                WindowType::Adaptive { m: 2 },
+                Bandwidth::MaxNeighbor
+            ),
+            [0.75, 0.5]
+        );
+        assert_eq!(
+            weights(&[0.0, 0.0], WindowType::Unfixed, Bandwidth::MaxNeighbor),
+            [1.0, 1.0]
+        );
+        assert!(weights(&[], WindowType::Unfixed, Bandwidth::MaxNeighbor).is_empty());
+    }
+
+    #[test]
+    fn neighbours_carry_every_field_of_a_known_neighbourhood() {
+        let mut knn: Knn<Manhattan, Diagnosis, 2> =
+            Knn::with_params(1, 2.5, &WindowType::Fixed, kernel::Triangular).unwrap();
+        knn.fit(line(), Some(vec![1.0, 2.0, 0.5, 1.0, 1.0]), false)
+            .unwrap();
+
+        let neighbours = knn.predict_with_neighbors(&[0.4, 0.0]).unwrap();
+
+        let expected = [
+            (0, 0.4, 1.0, Diagnosis::Benign),
+            (1, 0.6, 2.0, Diagnosis::Benign),
+            (2, 1.6, 0.5, Diagnosis::Benign),
+        ];
+        assert_eq!(neighbours.len(), expected.len());
+        for (neighbour, (index, distance, sample_weight, label)) in neighbours.iter().zip(expected)
+        {
+            assert_eq!(neighbour.index, index);
+            assert_eq!(neighbour.label, label);
+            assert!((neighbour.raw_distance - distance).abs() < 1e-12);
+            assert!((neighbour.normalized_distance - distance / 2.5).abs() < 1e-12);
+            assert!((neighbour.kernel_weight - (1.0 - distance / 2.5)).abs() < 1e-12);
+            assert!((neighbour.sample_weight - sample_weight).abs() < f64::EPSILON);
+        }
+    }
+
+    #[test]
+    fn neighbours_vote_with_the_standalone_kernel_weights() {
+        let windows = [
+            WindowType::Fixed,
+            WindowType::Unfixed,
+            WindowType::Capped {
+                radius: 9.5,
+                max_k: 3,
+            },
+            WindowType::Adaptive { m: 3 },
+        ];
+
+        for window in windows {
+            for bandwidth in [Bandwidth::MaxNeighbor, Bandwidth::MedianNeighbor] {
+                let mut knn: Knn<Manhattan, Diagnosis, 2> = KnnBuilder::new()
+                    .k(4)
+                    .radius(9.5)
+                    .window(window)
+                    .kernel(kernel::Epanechnikov)
+                    .build()
+                    .unwrap();
+                knn.set_bandwidth(bandwidth);
+                knn.fit(line(), None, false).unwrap();
+
+                let query = [1.5, 0.0];
+                // with the adaptive window's bandwidth point
+                let (distances, _) = knn.neighbors_excluding(&query, None);
+                let neighbours = knn.predict_with_neighbors(&query).unwrap();
+                let (window_distances, window_indices) = knn.neighbors(&query);
+
+                assert_eq!(
+                    neighbours
+                        .iter()
+                        .map(|neighbour| (neighbour.index, neighbour.raw_distance))
+                        .collect::<Vec<_>>(),
+                    window_indices
+                        .into_iter()
+                        .zip(window_distances)
+                        .collect::<Vec<_>>()
+                );
+                assert_eq!(
+                    neighbours
+                        .iter()
+                        .map(|neighbour| neighbour.kernel_weight)
+                        .collect::<Vec<_>>(),
+                    kernel_weights(&distances, window, 9.5, bandwidth, &kernel::Epanechnikov),
+                    "{window:?} {bandwidth:?}"
+                );
+            }
+        }
+    }
+
+    /// Bandwidth the nearest neighbour of the origin was normalized by, with
+    /// the farthest of five neighbours at `last`
+    fn bandwidth_with_last_neighbour_at(bandwidth: Bandwidth, last: f64) -> f64 {
+        let mut knn: Knn<Manhattan, Diagnosis, 2> =
+            Knn::with_params(5, 0.0, &WindowType::Unfixed, kernel::Epanechnikov).unwrap();
+        knn.set_bandwidth(bandwidth);
+        knn.fit(
+            [1.0, 1.1, 1.2, 1.3, last]
+                .into_iter()
+                .map(|x| Data {
+                    features: [x, 0.0],
+                    label: Diagnosis::Benign,
+                })
+                .collect(),
+            None,
+            false,
+        )
+        .unwrap();
+
+        let nearest = knn.predict_with_neighbors(&[0.0, 0.0]).unwrap()[0];
+        nearest.raw_distance / nearest.normalized_distance
+    }
+
+    #[test]
+    fn median_bandwidth_shrugs_off_a_far_outlier() {
+        let max_close = bandwidth_with_last_neighbour_at(Bandwidth::MaxNeighbor, 1.4);
+        let max_far = bandwidth_with_last_neighbour_at(Bandwidth::MaxNeighbor, 100.0);
+        let median_close = bandwidth_with_last_neighbour_at(Bandwidth::MedianNeighbor, 1.4);
+        let median_far = bandwidth_with_last_neighbour_at(Bandwidth::MedianNeighbor, 100.0);
+
+        assert!(max_far / max_close > 50.0);
+        assert!((median_far - median_close).abs() < 1e-9);
+        assert!((median_close - 1.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn suggested_radii_reach_within_clusters_but_not_across() {
+        // spacings of 0.1 and 0.3, 10 apart
+        let data: Vec<Data<Diagnosis, 2>> = (0..10)
+            .map(|step| ([f64::from(step) * 0.1, 0.0], Diagnosis::Benign))
+            .chain((0..10).map(|step| ([10.0 + f64::from(step) * 0.3, 0.0], Diagnosis::Malignant)))
+            .map(|(features, label)| Data { features, label })
+            .collect();
+        let mut knn: Knn<Manhattan, Diagnosis, 2> =
+            Knn::with_params(1, 0.0, &WindowType::Unfixed, kernel::Uniform).unwrap();
+        assert!(knn.suggest_radius(0.9).is_nan());
+        knn.fit(data.clone(), None, false).unwrap();
+
+        let radius = knn.suggest_radius(0.9);
+
+        assert!((0.1..10.0).contains(&radius), "{radius}");
+        knn.set_radius(radius * 1.01).unwrap();
+        knn.set_window(WindowType::Fixed).unwrap();
+        for data_point in &data {
+            let (_, indices) = knn.neighbors(&data_point.features);
+
+            assert!(indices.len() >= 2);
+            assert!(indices
+                .iter()
+                .all(|&index| data[index].label == data_point.label));
+        }
+    }
+
+    #[test]
+    fn neighbour_distance_quantiles_summarize_the_k_distances() {
+        let mut knn: Knn<Manhattan, Diagnosis, 2> =
+            Knn::with_params(1, 0.0, &WindowType::Unfixed, kernel::Uniform).unwrap();
+        assert!(knn
+            .neighbor_distance_quantiles(2)
+            .iter()
+            .all(|quantile| quantile.is_nan()));
+
+        knn.fit(line(), None, false).unwrap();
+        let quantiles = knn.neighbor_distance_quantiles(2);
+
+        // second nearest other points lie 2, 1, 2, 8 and 9 away
+        assert!(quantiles
+            .iter()
+            .zip([1.0, 2.0, 2.0, 8.0, 9.0])
+            .all(|(quantile, expected)| (quantile - expected).abs() < 1e-12));
+        assert!(quantiles.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+
+    #[test]
+    fn points_on_a_plane_have_an_intrinsic_dimension_near_two() {
+        use kiddo::SquaredEuclidean;
+        use rand::Rng;
+
+        let mut rng = StdRng::seed_from_u64(3);
+        let directions: [[f64; 30]; 2] =
+            [(); 2].map(|()| [(); 30].map(|()| rng.gen_range(-1.0..1.0)));
+        let data: Vec<Data<Diagnosis, 30>> = (0..400)
+            .map(|_| {
+                let (a_val, b_val): (f64, f64) = (rng.gen(), rng.gen());
+                let mut features = [0.0; 30];
+                for (axis, feature) in features.iter_mut().enumerate() {
+                    *feature = a_val * directions[0][axis] + b_val * directions[1][axis];
+                }
+
+                Data {
+                    features,
+                    label: Diagnosis::Benign,
+                }
+            })
+            .collect();
+
+        let mut knn: Knn<SquaredEuclidean, Diagnosis, 30> =
+            Knn::with_params(3, 0.0, &WindowType::Unfixed, kernel::Uniform).unwrap();
+        knn.fit(data, None, false).unwrap();
+
+        let estimate = knn.intrinsic_dimension(10);
+        assert!((1.5..3.0).contains(&estimate), "{estimate}");
+    }
+
+    #[test]
+    fn canonical_order_makes_predictions_independent_of_input_order() {
+        // every query between two points sits exactly between both classes
+        let data = labelled(&[
+            ([-1.0, 0.0], Diagnosis::Benign),
+            ([1.0, 0.0], Diagnosis::Malignant),
+            ([0.0, -1.0], Diagnosis::Malignant),
+            ([0.0, 1.0], Diagnosis::Benign),
+            ([3.0, 0.0], Diagnosis::Benign),
+            ([5.0, 0.0], Diagnosis::Malignant),
+        ]);
+        let mut shuffled = data.clone();
+        shuffled.shuffle(&mut StdRng::seed_from_u64(11));
+        let mut reversed = data.clone();
+        reversed.reverse();
+
+        let fitted = |data: Vec<Data<Diagnosis, 2>>| {
+            let mut knn: Knn<Manhattan, Diagnosis, 2> =
+                Knn::with_params(1, 0.0, &WindowType::Unfixed, kernel::Uniform).unwrap();
+            knn.set_canonical_order(true);
+            knn.fit(data, None, false).unwrap();
+            knn
+        };
+        let models = [fitted(data), fitted(shuffled), fitted(reversed)];
+
+        for query in [[0.0, 0.0], [4.0, 0.0], [2.0, 0.0], [0.5, 0.5]] {
+            let expected = models[0].predict(&query);
+            for knn in &models[1..] {
+                assert_eq!(knn.predict(&query), expected);
+                assert_eq!(knn.predict_proba(&query), models[0].predict_proba(&query));
+            }
+        }
+    }
+
+    #[test]
+    fn class_order_is_stable_across_calls() {
+        let mut knn: Knn<Manhattan, Diagnosis, 2> =
+            Knn::with_params(3, 0.0, &WindowType::Unfixed, kernel::Uniform).unwrap();
+        // the first training label is the one that sorts last
+        knn.fit(line().into_iter().rev().collect(), None, false)
+            .unwrap();
+
+        assert_eq!(knn.classes(), [Diagnosis::Malignant, Diagnosis::Benign]);
+        for query in [[0.5, 0.0], [10.5, 0.0], [6.0, 0.0], [0.5, 0.0]] {
+            assert_eq!(knn.predict_proba_dense(&query).unwrap().len(), 2);
+            assert_eq!(knn.classes(), [Diagnosis::Malignant, Diagnosis::Benign]);
+        }
+    }
+
+    #[test]
+    fn dense_probabilities_agree_with_the_sparse_ones() {
+        let mut knn: Knn<Manhattan, Diagnosis, 2> =
+            Knn::with_params(2, 0.0, &WindowType::Unfixed, kernel::Uniform).unwrap();
+        knn.fit(line(), None, false).unwrap();
+
+        for query in [[0.5, 0.0], [10.5, 0.0], [6.0, 0.0]] {
+            let sparse = knn.predict_proba(&query).unwrap();
+            let dense = knn.predict_proba_dense(&query).unwrap();
+
+            for (class, probability) in knn.classes().iter().zip(dense) {
+                let expected = sparse.get(class).copied().unwrap_or(0.0);
+                assert!((probability - expected).abs() < f64::EPSILON);
+            }
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn saved_models_load_with_identical_predictions() {
+        let mut knn: Knn<Manhattan, Diagnosis, 2> = KnnBuilder::new()
+            .k(3)
+            .window(WindowType::Unfixed)
+            .kernel(kernel::Epanechnikov)
+            .bucket_size(BucketSize::B8)
+            .build()
+            .unwrap();
+        knn.set_smoothing(1.0);
+        knn.set_canonical_order(true);
+        knn.set_kernel_check(KernelCheck::Strict);
+        knn.set_backend(Backend::BruteForce);
+        let mut data = line();
+        data.reverse();
+        knn.fit(data, Some(vec![1.0, 2.0, 1.0, 3.0, 1.0]), false)
+            .unwrap();
+        let unbiased = knn.clone();
+        knn.set_class_bias(Diagnosis::Malignant, 0.3);
+
+        let path = std::env::temp_dir().join(format!("knn-model-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        knn.save(path).unwrap();
+        let loaded = Knn::<Manhattan, Diagnosis, 2>::load(path);
+        std::fs::remove_file(path).unwrap();
+        let loaded = loaded.unwrap();
+
+        assert_eq!(loaded.class_bias(), knn.class_bias());
+        assert_eq!(loaded.bucket_size(), BucketSize::B8);
+        assert!(loaded.canonical_order);
+        assert_eq!(loaded.kernel_check(), KernelCheck::Strict);
+        assert_eq!(loaded.backend(), Backend::BruteForce);
+
+        let queries: Vec<[f64; 2]> = (0..=24).map(|step| [f64::from(step) * 0.5, 0.5]).collect();
+        for query in &queries {
+            assert_eq!(loaded.predict(query), knn.predict(query));
+            assert_eq!(loaded.predict_proba(query), knn.predict_proba(query));
+        }
+        // the bias has to matter for the comparison to cover it
+        assert!(queries
+            .iter()
+            .any(|query| knn.predict(query) != unbiased.predict(query)));
     }
 }
@@ -2,4 +2,13 @@ pub mod distance_metric;
 pub mod kernel;
 pub mod knn;
 pub mod lowess;
+pub mod metrics;
+pub mod monitor;
 pub mod parse;
+pub mod plot;
+pub mod regression;
+pub mod search;
+pub mod selection;
+pub mod tree;
+pub mod validation;
+pub mod whitening;
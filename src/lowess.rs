@@ -1,41 +1,159 @@
-use crate::knn::{Data, Knn, WindowType, DIMENSIONS};
+use crate::{
+    distance_metric::MetricKind,
+    kernel::Kernel,
+    knn::{Data, Knn, KnnError, Label, Scalar, WindowType},
+};
 
-pub fn lowess<M>(
+/// Leave-one-out weight of every training point, `kernel(0)` when the rest of
+/// the data predicts its label and `kernel(1)` otherwise. Each of the
+/// `iterations` further passes refits with the current weights and multiplies
+/// them by that pass's leave-one-out weights, so points that keep being
+/// misclassified sink towards zero relative to the rest. 0 iterations is a
+/// single unweighted pass
+pub fn lowess<M, L, const D: usize, T>(
     neighbour_amount: usize,
     radius: f64,
     window_type: WindowType,
-    kernel: fn(f64) -> f64,
-    train_data: &[Data],
+    kernel: impl Kernel + 'static,
+    train_data: &[Data<L, D, T>],
+    iterations: usize,
+) -> Result<Vec<f64>, KnnError>
+where
+    M: kiddo::distance_metric::DistanceMetric<T, D> + MetricKind,
+    L: Label,
+    T: Scalar,
+{
+    let pass_weights = [kernel.eval(0.0), kernel.eval(1.0)];
+    let mut knn_instance: Knn<M, L, D, T> =
+        Knn::with_params(neighbour_amount, radius, &window_type, kernel)?;
+    knn_instance.fit(train_data.to_vec(), None, false)?;
+    let mut weights = leave_one_out_weights(&knn_instance, pass_weights, train_data);
+
+    for _ in 0..iterations {
+        knn_instance.fit(train_data.to_vec(), Some(weights.clone()), false)?;
+
+        for (weight, pass_weight) in weights.iter_mut().zip(leave_one_out_weights(
+            &knn_instance,
+            pass_weights,
+            train_data,
+        )) {
+            *weight *= pass_weight;
+        }
+    }
+
+    Ok(weights)
+}
+
+fn leave_one_out_weights<M, L, const D: usize, T>(
+    knn_instance: &Knn<M, L, D, T>,
+    [correct, misclassified]: [f64; 2],
+    train_data: &[Data<L, D, T>],
 ) -> Vec<f64>
 where
-    M: kiddo::distance_metric::DistanceMetric<f64, DIMENSIONS>,
+    M: kiddo::distance_metric::DistanceMetric<T, D> + MetricKind,
+    L: Label,
+    T: Scalar,
 {
-    let mut weights = Vec::with_capacity(train_data.len());
-
-    for (i, data_point) in train_data.iter().enumerate() {
-        let mut modified_train_data = train_data.to_vec();
-        modified_train_data.remove(i);
-
-        let mut knn_instance: Knn<M> = Knn::new(
-            neighbour_amount,
-            radius,
-            &window_type,
-            kernel,
-            modified_train_data.len(),
-        );
-        knn_instance.fit(modified_train_data, None);
-
-        match knn_instance.predict(&data_point.features) {
-            Ok(prediction) => {
-                let weight = if prediction == data_point.label {
-                    kernel(0.0)
+    train_data
+        .iter()
+        .enumerate()
+        .map(
+            |(i, data_point)| match knn_instance.predict_excluding(&data_point.features, i) {
+                Ok(prediction) if prediction == data_point.label => correct,
+                Ok(_) => misclassified,
+                Err(_) => 0.0,
+            },
+        )
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use kiddo::Manhattan;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::{
+        kernel::{epanechnikov, gaussian},
+        parse::breast_cancer::Diagnosis,
+    };
+
+    /// Two overlapping classes, so some points are outvoted by their
+    /// neighbours
+    fn noisy_data() -> Vec<Data<Diagnosis, 2>> {
+        let mut rng = StdRng::seed_from_u64(0);
+
+        (0..60)
+            .map(|_| {
+                let features = [rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0)];
+                let label = if features[0] + rng.gen_range(-0.5..0.5) > 0.0 {
+                    Diagnosis::Malignant
                 } else {
-                    kernel(1.0)
+                    Diagnosis::Benign
                 };
-                weights.push(weight);
-            }
-            Err(_) => weights.push(0.0),
+
+                Data { features, label }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn weights_match_refitting_without_each_point() {
+        let data = noisy_data();
+        let weights = lowess::<Manhattan, Diagnosis, 2, _>(
+            5,
+            1.0,
+            WindowType::Unfixed,
+            epanechnikov,
+            &data,
+            0,
+        )
+        .unwrap();
+
+        for (index, data_point) in data.iter().enumerate() {
+            let rest: Vec<_> = data[..index]
+                .iter()
+                .chain(&data[index + 1..])
+                .copied()
+                .collect();
+            let mut knn: Knn<Manhattan, Diagnosis, 2> =
+                Knn::with_params(5, 1.0, &WindowType::Unfixed, epanechnikov).unwrap();
+            knn.fit(rest, None, false).unwrap();
+
+            let expected = match knn.predict(&data_point.features) {
+                Ok(prediction) if prediction == data_point.label => epanechnikov(0.0),
+                Ok(_) => epanechnikov(1.0),
+                Err(_) => 0.0,
+            };
+            assert!((weights[index] - expected).abs() < 1e-12, "point {index}");
         }
     }
-    weights
+
+    /// The gaussian keeps misclassified points' weights positive, so a
+    /// second pass can push them further down
+    #[test]
+    fn iterations_sink_points_misclassified_in_every_pass() {
+        let data = noisy_data();
+        let lowess_with = |iterations| {
+            lowess::<Manhattan, Diagnosis, 2, _>(
+                5,
+                1.0,
+                WindowType::Unfixed,
+                gaussian,
+                &data,
+                iterations,
+            )
+            .unwrap()
+        };
+        let (once, twice) = (lowess_with(1), lowess_with(2));
+        let misclassified_twice = gaussian(1.0).powi(2);
+
+        let sunk = once
+            .iter()
+            .zip(&twice)
+            .filter(|(once, _)| (**once - misclassified_twice).abs() < 1e-12)
+            .filter(|(once, twice)| **twice < **once * gaussian(0.0))
+            .count();
+        assert!(sunk > 0);
+    }
 }
@@ -1,10 +1,17 @@
-use kiddo::{Manhattan, SquaredEuclidean};
+use kiddo::{distance_metric::DistanceMetric, Manhattan, SquaredEuclidean};
 use knn::{
-    distance_metric::Chebyshev,
-    kernel::{epanechnikov, gaussian, triangular, uniform},
-    knn::{Data, Knn, WindowType, DIMENSIONS},
+    distance_metric::{Chebyshev, MetricKind},
+    kernel::{Epanechnikov, Gaussian, Kernel, Quartic, Triangular, Tricube, Triweight, Uniform},
+    knn::{Data, Knn, KnnBuilder, KnnError, VoteWeighting, WindowType},
     lowess::lowess,
-    parse::breast_cancer::{opposite_diagnosis, parse, CsvEntry, Diagnosis},
+    metrics::{auc, calculate_accuracy, f1_score, reliability_diagram, roc_curve},
+    monitor::DriftDetector,
+    parse::breast_cancer::{opposite_diagnosis, parse, CsvEntry, Diagnosis, DIMENSIONS},
+    plot::{plot_param_importance, plot_reliability_diagram, plot_residuals, plot_roc_curve},
+    regression::KnnRegressor,
+    search::{
+        best_candidate_preferring_larger_k, GridSearch, Hyperparameters, SearchRecord, SearchResult,
+    },
 };
 use plotters::{
     chart::ChartBuilder,
@@ -12,19 +19,35 @@ use plotters::{
     series::LineSeries,
     style::{IntoFont, BLACK, BLUE, RED, WHITE},
 };
-use std::error::Error;
+use rayon::prelude::*;
+use std::{error::Error, sync::Arc};
 
-fn csv_entries_to_data(entries: Vec<CsvEntry>) -> Vec<Data> {
+fn csv_entries_to_data(
+    entries: Vec<CsvEntry>,
+) -> Result<Vec<Data<Diagnosis, DIMENSIONS>>, Box<dyn Error>> {
     entries
         .into_iter()
-        .map(|entry| Data {
-            features: entry.values.try_into().unwrap(),
-            label: entry.diagnosis,
+        .map(|entry| {
+            let width = entry.values.len();
+
+            Ok(Data {
+                features: entry
+                    .values
+                    .try_into()
+                    .map_err(|_| format!("expected {DIMENSIONS} features, got {width}"))?,
+                label: entry.diagnosis,
+            })
         })
         .collect()
 }
 
-fn split_data(data: &[Data], train_ratio: f64) -> (Vec<Data>, Vec<Data>) {
+fn split_data(
+    data: &[Data<Diagnosis, DIMENSIONS>],
+    train_ratio: f64,
+) -> (
+    Vec<Data<Diagnosis, DIMENSIONS>>,
+    Vec<Data<Diagnosis, DIMENSIONS>>,
+) {
     #[allow(clippy::cast_possible_truncation)]
     #[allow(clippy::cast_sign_loss)]
     let train_size = (data.len() as f64 * train_ratio) as usize;
@@ -33,144 +56,115 @@ fn split_data(data: &[Data], train_ratio: f64) -> (Vec<Data>, Vec<Data>) {
     (train_data.to_vec(), test_data.to_vec())
 }
 
-fn calculate_accuracy<M>(knn: &Knn<M>, test_data: &[Data]) -> f64
-where
-    M: kiddo::distance_metric::DistanceMetric<f64, DIMENSIONS>,
-{
-    let mut predictions = Vec::new();
-    let actuals: Vec<Diagnosis> = test_data
-        .iter()
-        .map(|test_point| test_point.label)
-        .collect();
-
-    for test_point in test_data {
-        match knn.predict(&test_point.features) {
-            Ok(prediction) => predictions.push(Some(prediction)),
-            Err(_) => predictions.push(None),
-        }
-    }
-
-    let correct_predictions = predictions
-        .iter()
-        .zip(actuals.iter())
-        .filter(|&(prediction, actual)| match prediction {
-            Some(prediction) => prediction == actual,
-            _ => false,
-        })
-        .count();
-
-    let total_predictions = predictions.len();
-
-    if total_predictions > 0 {
-        (correct_predictions as f64 / total_predictions as f64) * 100.0
-    } else {
-        0.0
-    }
-}
-
-#[allow(clippy::too_many_arguments)]
-fn update_max_accuracy_and_print(
+/// Validation accuracy of a grid point and how many training points its
+/// queries examined on average
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Evaluation {
     accuracy: f64,
-    max_accuracy: &mut f64,
-    count: &mut usize,
-    best_hyperparameters: &mut Hyperparameters,
-    kernel_name: &str,
-    kernel_function: fn(f64) -> f64,
-    window_name: &str,
-    window_type: WindowType,
-    neighbour_amount: usize,
-    radius: usize,
-    metric: &str,
-) {
-    *count += 1;
-
-    if accuracy > *max_accuracy {
-        *max_accuracy = accuracy;
-
-        best_hyperparameters.window = window_type;
-        best_hyperparameters.k = neighbour_amount;
-        best_hyperparameters.radius = radius as f64;
-        best_hyperparameters.kernel = kernel_function;
-        best_hyperparameters.metric = metric.to_string();
+    mean_points_examined: f64,
+}
 
-        println!(
-            "{count}. kernel: {kernel_name}, window: {window_name}, neighbours: {neighbour_amount}, radius: {radius}, metric: {metric}\taccuracy: {accuracy:.3}%",
-        );
+/// [`Evaluation`] of every grid point in order. The training data is fitted
+/// once per metric, grid points only change the query parameters
+fn evaluate_candidates(
+    candidates: &[Hyperparameters],
+    train_data: &[Data<Diagnosis, DIMENSIONS>],
+    validation_data: &[Data<Diagnosis, DIMENSIONS>],
+) -> Result<Vec<Evaluation>, KnnError> {
+    fn evaluate<M: DistanceMetric<f64, DIMENSIONS> + MetricKind + Send>(
+        candidates: &[&Hyperparameters],
+        train_data: &[Data<Diagnosis, DIMENSIONS>],
+        validation_data: &[Data<Diagnosis, DIMENSIONS>],
+    ) -> Result<Vec<Evaluation>, KnnError> {
+        let mut fitted: Knn<M, Diagnosis, DIMENSIONS> =
+            Knn::with_params(1, 0.0, &WindowType::Unfixed, Uniform)?;
+        fitted.fit(train_data.to_vec(), None, false)?;
+        fitted.set_instrumented(true);
+
+        candidates
+            .par_iter()
+            .map_with(fitted, |knn, hyperparameters| {
+                knn.set_k(hyperparameters.k)?;
+                knn.set_radius(hyperparameters.radius)?;
+                knn.set_window(hyperparameters.window)?;
+                knn.set_kernel(Arc::clone(&hyperparameters.kernel));
+                knn.set_vote_weighting(hyperparameters.voting)?;
+                knn.reset_query_stats();
+
+                Ok(Evaluation {
+                    accuracy: calculate_accuracy(knn, validation_data),
+                    mean_points_examined: knn.query_stats().mean_points_examined(),
+                })
+            })
+            .collect()
     }
-}
 
-#[derive(Debug)]
-struct Hyperparameters {
-    k: usize,
-    radius: f64,
-    window: WindowType,
-    kernel: fn(f64) -> f64,
-    metric: String,
-}
+    const METRICS: [&str; 3] = ["manhattan", "squared euclidean", "chebyshev"];
 
-impl Hyperparameters {
-    fn new() -> Self {
-        Self {
-            k: 0,
-            radius: 0.0,
-            window: WindowType::Fixed,
-            kernel: uniform,
-            metric: String::new(),
-        }
+    if let Some(unknown) = candidates
+        .iter()
+        .find(|hyperparameters| !METRICS.contains(&hyperparameters.metric.as_str()))
+    {
+        return Err(KnnError::InvalidInput(format!(
+            "unexpected distance metric {}",
+            unknown.metric
+        )));
     }
-}
 
-fn calculate_f1_score(data: &[Data], predictions: &[Diagnosis]) -> f64 {
-    let mut true_positive_count = 0;
-    let mut false_positive_count = 0;
-    let mut false_negative_count = 0;
+    let mut evaluations = vec![
+        Evaluation {
+            accuracy: 0.0,
+            mean_points_examined: 0.0,
+        };
+        candidates.len()
+    ];
+
+    for metric in METRICS {
+        let (indices, group): (Vec<usize>, Vec<&Hyperparameters>) = candidates
+            .iter()
+            .enumerate()
+            .filter(|(_, hyperparameters)| hyperparameters.metric == metric)
+            .unzip();
 
-    for (actual, predicted) in data.iter().zip(predictions.iter()) {
-        if actual.label == *predicted {
-            true_positive_count += 1;
-        } else {
-            match predicted {
-                Diagnosis::Malignant => {
-                    false_positive_count += 1;
-                }
-                Diagnosis::Benign => {
-                    false_negative_count += 1;
-                }
+        let group_evaluations = match metric {
+            "manhattan" => evaluate::<Manhattan>(&group, train_data, validation_data)?,
+            "squared euclidean" => {
+                evaluate::<SquaredEuclidean>(&group, train_data, validation_data)?
             }
+            _ => evaluate::<Chebyshev>(&group, train_data, validation_data)?,
+        };
+
+        for (index, evaluation) in indices.into_iter().zip(group_evaluations) {
+            evaluations[index] = evaluation;
         }
     }
 
-    let precision = if true_positive_count + false_positive_count > 0 {
-        true_positive_count as f64 / (true_positive_count + false_positive_count) as f64
-    } else {
-        0.0
-    };
-    let recall = if true_positive_count + false_negative_count > 0 {
-        true_positive_count as f64 / (true_positive_count + false_negative_count) as f64
-    } else {
-        0.0
-    };
-
-    if precision + recall > 0.0 {
-        2.0 * (precision * recall) / (precision + recall)
-    } else {
-        0.0
-    }
+    Ok(evaluations)
 }
 
 #[allow(clippy::too_many_lines)]
 fn main() -> Result<(), Box<dyn Error>> {
     const DATA_FILEPATH: &str = "data/breast-cancer.csv";
     const PLOT_FILENAME: &str = "plot.png";
+    const IMPORTANCE_PLOT_FILENAME: &str = "importance.png";
+    const CALIBRATION_PLOT_FILENAME: &str = "calibration.png";
+    const ROC_PLOT_FILENAME: &str = "roc.png";
+    const RESIDUALS_PLOT_FILENAME: &str = "residuals.png";
+    const CALIBRATION_BINS: usize = 10;
+    const TRAIN_RATIO: f64 = 0.6;
+    const VALIDATION_RATIO: f64 = 0.6; // of data that is not train
+    const TOP_CANDIDATES: usize = 10;
+    // percentage points of validation accuracy worth trading for a larger k
+    const ACCURACY_TOLERANCE: f64 = 0.5;
+    const IMPORTANCE_SEED: u64 = 0;
+    const TOP_FEATURES: usize = 5;
 
     let entries = parse(DATA_FILEPATH)?;
-    assert!(!entries.is_empty());
-    assert_eq!(entries.first().unwrap().values.len(), DIMENSIONS);
-
-    let data = csv_entries_to_data(entries);
+    if entries.is_empty() {
+        return Err(format!("{DATA_FILEPATH} has no entries").into());
+    }
 
-    const TRAIN_RATIO: f64 = 0.6;
-    const VALIDATION_RATIO: f64 = 0.6; // of data that is not train
+    let data = csv_entries_to_data(entries)?;
 
     let (train_data, test_data) = split_data(&data, TRAIN_RATIO);
     let (test_data, validation_data) = split_data(&test_data, VALIDATION_RATIO);
@@ -178,103 +172,119 @@ fn main() -> Result<(), Box<dyn Error>> {
     println!("test_data.len() : {}", test_data.len());
     println!("validation_data.len() : {}", validation_data.len());
 
-    let kernel_functions: [(&str, fn(f64) -> f64); 4] = [
-        ("uniform", uniform),
-        ("triangular", triangular),
-        ("epanechnikov", epanechnikov),
-        ("gaussian", gaussian),
+    let votings: [(&str, Arc<dyn Kernel>, VoteWeighting); 8] = [
+        ("uniform", Arc::new(Uniform), VoteWeighting::Kernel),
+        ("triangular", Arc::new(Triangular), VoteWeighting::Kernel),
+        (
+            "epanechnikov",
+            Arc::new(Epanechnikov),
+            VoteWeighting::Kernel,
+        ),
+        ("quartic", Arc::new(Quartic), VoteWeighting::Kernel),
+        ("triweight", Arc::new(Triweight), VoteWeighting::Kernel),
+        ("tricube", Arc::new(Tricube), VoteWeighting::Kernel),
+        ("gaussian", Arc::new(Gaussian), VoteWeighting::Kernel),
+        (
+            "inverse distance",
+            Arc::new(Uniform),
+            VoteWeighting::InverseDistance { epsilon: 1e-9 },
+        ),
     ];
     let window_types = [
         ("fixed", WindowType::Fixed),
         ("unfixed", WindowType::Unfixed),
     ];
 
-    let mut max_accuracy = 0.0;
-    let mut count = 0;
-    let mut best_hyperparameters = Hyperparameters::new();
+    let grid = GridSearch::new((1..50).collect(), (1..15).map(f64::from).collect())?;
 
-    for radius in 1..15 {
-        for neighbour_amount in 1..50 {
+    let mut candidates = Vec::new();
+    for &radius in grid.radii() {
+        for &neighbour_amount in grid.ks() {
             for (window_name, window_type) in &window_types {
-                for (kernel_name, kernel_function) in &kernel_functions {
-                    let mut knn_manhattan: Knn<Manhattan> = Knn::new(
-                        neighbour_amount,
-                        radius as f64,
-                        window_type,
-                        *kernel_function,
-                        train_data.len(),
-                    );
-                    knn_manhattan.fit(train_data.clone(), None);
-                    let accuracy = calculate_accuracy(&knn_manhattan, &validation_data);
-
-                    update_max_accuracy_and_print(
-                        accuracy,
-                        &mut max_accuracy,
-                        &mut count,
-                        &mut best_hyperparameters,
-                        kernel_name,
-                        *kernel_function,
-                        window_name,
-                        *window_type,
-                        neighbour_amount,
-                        radius,
-                        "manhattan",
-                    );
-
-                    let mut knn_squared_euclidean: Knn<SquaredEuclidean> = Knn::new(
-                        neighbour_amount,
-                        radius as f64,
-                        window_type,
-                        *kernel_function,
-                        train_data.len(),
-                    );
-                    knn_squared_euclidean.fit(train_data.clone(), None);
-                    let accuracy = calculate_accuracy(&knn_squared_euclidean, &validation_data);
-
-                    update_max_accuracy_and_print(
-                        accuracy,
-                        &mut max_accuracy,
-                        &mut count,
-                        &mut best_hyperparameters,
-                        kernel_name,
-                        *kernel_function,
-                        window_name,
-                        *window_type,
-                        neighbour_amount,
-                        radius,
-                        "squared euclidean",
-                    );
-
-                    let mut knn_chebyshev: Knn<Chebyshev> = Knn::new(
-                        neighbour_amount,
-                        radius as f64,
-                        window_type,
-                        *kernel_function,
-                        train_data.len(),
-                    );
-                    knn_chebyshev.fit(train_data.clone(), None);
-                    let accuracy = calculate_accuracy(&knn_chebyshev, &validation_data);
-
-                    update_max_accuracy_and_print(
-                        accuracy,
-                        &mut max_accuracy,
-                        &mut count,
-                        &mut best_hyperparameters,
-                        kernel_name,
-                        *kernel_function,
-                        window_name,
-                        *window_type,
-                        neighbour_amount,
-                        radius,
-                        "chebyshev",
-                    );
+                for (kernel_name, kernel, voting) in &votings {
+                    for metric in ["manhattan", "squared euclidean", "chebyshev"] {
+                        let hyperparameters = Hyperparameters {
+                            k: neighbour_amount,
+                            radius,
+                            window: *window_type,
+                            kernel: Arc::clone(kernel),
+                            voting: *voting,
+                            metric: metric.to_string(),
+                        };
+                        candidates.push((hyperparameters, *kernel_name, *window_name));
+                    }
                 }
             }
         }
     }
 
+    let hyperparameters: Vec<Hyperparameters> = candidates
+        .iter()
+        .map(|(hyperparameters, _, _)| hyperparameters.clone())
+        .collect();
+    let evaluations = evaluate_candidates(&hyperparameters, &train_data, &validation_data)?;
+    let accuracies: Vec<f64> = evaluations
+        .iter()
+        .map(|evaluation| evaluation.accuracy)
+        .collect();
+
+    let mut search_result = SearchResult::new();
+    let mut max_accuracy = 0.0;
+    for (count, ((hyperparameters, kernel_name, window_name), evaluation)) in
+        candidates.iter().zip(&evaluations).enumerate()
+    {
+        let accuracy = evaluation.accuracy;
+        search_result.push(SearchRecord {
+            k: hyperparameters.k,
+            radius: hyperparameters.radius,
+            kernel: (*kernel_name).to_string(),
+            window: hyperparameters.window,
+            metric: hyperparameters.metric.clone(),
+            score: accuracy,
+            mean_points_examined: Some(evaluation.mean_points_examined),
+        });
+
+        if accuracy > max_accuracy {
+            max_accuracy = accuracy;
+
+            println!(
+                "{}. kernel: {kernel_name}, window: {window_name}, neighbours: {}, radius: {}, metric: {}\taccuracy: {accuracy:.3}%",
+                count + 1,
+                hyperparameters.k,
+                hyperparameters.radius,
+                hyperparameters.metric,
+            );
+        }
+    }
+
+    let ks: Vec<usize> = candidates
+        .iter()
+        .map(|(hyperparameters, _, _)| hyperparameters.k)
+        .collect();
+    let best_hyperparameters =
+        best_candidate_preferring_larger_k(&accuracies, &ks, ACCURACY_TOLERANCE)
+            .map(|index| candidates[index].0.clone())
+            .ok_or("empty search grid")?;
+
     println!("best hyperparameters: {best_hyperparameters:?}");
 
+    println!("top candidates:");
+    for record in search_result.sorted().into_iter().take(TOP_CANDIDATES) {
+        println!("\t{record}");
+    }
+
+    let importances = search_result.param_importance();
+    println!("hyperparameter importance:");
+    for importance in &importances {
+        println!("{}: {:.3}", importance.axis, importance.importance);
+        for (level, mean) in &importance.level_means {
+            println!("\t{level}: {mean:.3}%");
+        }
+    }
+
+    plot_param_importance(&importances, IMPORTANCE_PLOT_FILENAME)?;
+    println!("importance plot saved to {IMPORTANCE_PLOT_FILENAME}");
+
     #[allow(clippy::items_after_statements)]
     const MAX_K: usize = 100;
 
@@ -285,14 +295,14 @@ fn main() -> Result<(), Box<dyn Error>> {
     for k in 1..MAX_K {
         let (train_predictions, test_predictions) = match best_hyperparameters.metric.as_str() {
             "manhattan" => {
-                let mut knn_manhattan: Knn<Manhattan> = Knn::new(
+                let mut knn_manhattan: Knn<Manhattan, Diagnosis, DIMENSIONS> = Knn::with_params(
                     k,
                     best_hyperparameters.radius,
                     &best_hyperparameters.window,
-                    best_hyperparameters.kernel,
-                    train_data.len(),
-                );
-                knn_manhattan.fit(train_data.clone(), None);
+                    Arc::clone(&best_hyperparameters.kernel),
+                )?;
+                knn_manhattan.set_vote_weighting(best_hyperparameters.voting)?;
+                knn_manhattan.fit(train_data.clone(), None, false)?;
 
                 let train_predictions: Vec<_> = train_data
                     .iter()
@@ -315,14 +325,15 @@ fn main() -> Result<(), Box<dyn Error>> {
                 (train_predictions, test_predictions)
             }
             "squared euclidean" => {
-                let mut knn_squared_euclidean: Knn<SquaredEuclidean> = Knn::new(
-                    k,
-                    best_hyperparameters.radius,
-                    &best_hyperparameters.window,
-                    best_hyperparameters.kernel,
-                    train_data.len(),
-                );
-                knn_squared_euclidean.fit(train_data.clone(), None);
+                let mut knn_squared_euclidean: Knn<SquaredEuclidean, Diagnosis, DIMENSIONS> =
+                    Knn::with_params(
+                        k,
+                        best_hyperparameters.radius,
+                        &best_hyperparameters.window,
+                        Arc::clone(&best_hyperparameters.kernel),
+                    )?;
+                knn_squared_euclidean.set_vote_weighting(best_hyperparameters.voting)?;
+                knn_squared_euclidean.fit(train_data.clone(), None, false)?;
 
                 let train_predictions: Vec<_> = train_data
                     .iter()
@@ -345,14 +356,14 @@ fn main() -> Result<(), Box<dyn Error>> {
                 (train_predictions, test_predictions)
             }
             "chebyshev" => {
-                let mut knn_chebyshev: Knn<Chebyshev> = Knn::new(
+                let mut knn_chebyshev: Knn<Chebyshev, Diagnosis, DIMENSIONS> = Knn::with_params(
                     k,
                     best_hyperparameters.radius,
                     &best_hyperparameters.window,
-                    best_hyperparameters.kernel,
-                    train_data.len(),
-                );
-                knn_chebyshev.fit(train_data.clone(), None);
+                    Arc::clone(&best_hyperparameters.kernel),
+                )?;
+                knn_chebyshev.set_vote_weighting(best_hyperparameters.voting)?;
+                knn_chebyshev.fit(train_data.clone(), None, false)?;
 
                 let train_predictions: Vec<_> = train_data
                     .iter()
@@ -374,15 +385,15 @@ fn main() -> Result<(), Box<dyn Error>> {
 
                 (train_predictions, test_predictions)
             }
-            _ => panic!("unexpected distance metric"),
+            metric => return Err(format!("unexpected distance metric {metric}").into()),
         };
 
-        let train_f1 = calculate_f1_score(&train_data, &train_predictions);
-        let test_f1 = calculate_f1_score(&test_data, &test_predictions);
+        let train_f1 = f1_score(&train_data, &train_predictions);
+        let test_f1 = f1_score(&test_data, &test_predictions);
 
         f1_train_values.push(train_f1);
         f1_test_values.push(test_f1);
-        k_values.push(k);
+        k_values.push(i32::try_from(k)?);
     }
 
     let root = BitMapBackend::new(PLOT_FILENAME, (1024, 768)).into_drawing_area();
@@ -402,7 +413,6 @@ fn main() -> Result<(), Box<dyn Error>> {
             k_values
                 .iter()
                 .copied()
-                .map(|k_val| i32::try_from(k_val).unwrap())
                 .zip(f1_train_values.iter().copied())
                 .collect::<Vec<_>>(),
             RED,
@@ -415,7 +425,6 @@ fn main() -> Result<(), Box<dyn Error>> {
             k_values
                 .iter()
                 .copied()
-                .map(|k_val| i32::try_from(k_val).unwrap())
                 .zip(f1_test_values.iter().copied())
                 .collect::<Vec<_>>(),
             BLUE,
@@ -431,23 +440,33 @@ fn main() -> Result<(), Box<dyn Error>> {
     // TODO: in case of dataset change add other distance metrics
     // for best_hyperparameters.metric
     // the amount of potential new code seems not justified for now
-    let mut knn_manhattan: Knn<Manhattan> = Knn::new(
-        best_hyperparameters.k,
-        best_hyperparameters.radius,
-        &best_hyperparameters.window,
-        best_hyperparameters.kernel,
-        train_data.len(),
-    );
-
-    let weights = lowess::<Manhattan>(
+    let mut knn_manhattan: Knn<Manhattan, Diagnosis, DIMENSIONS> = KnnBuilder::new()
+        .k(best_hyperparameters.k)
+        .radius(best_hyperparameters.radius)
+        .window(best_hyperparameters.window)
+        .kernel(Arc::clone(&best_hyperparameters.kernel))
+        .voting(best_hyperparameters.voting)
+        .build()?;
+
+    let weights = lowess::<Manhattan, Diagnosis, DIMENSIONS, _>(
         best_hyperparameters.k,
         best_hyperparameters.radius,
         best_hyperparameters.window,
-        best_hyperparameters.kernel,
+        Arc::clone(&best_hyperparameters.kernel),
         &train_data,
-    );
+        0,
+    )?;
+
+    knn_manhattan.fit(train_data.clone(), None, false)?;
 
-    knn_manhattan.fit(train_data.clone(), None);
+    println!(
+        "intrinsic dimension estimate: {:.2} of {DIMENSIONS}",
+        knn_manhattan.intrinsic_dimension(10)
+    );
+    println!(
+        "suggested fixed radius: {:.3}",
+        knn_manhattan.suggest_radius(0.9)
+    );
 
     let train_predictions: Vec<_> = train_data
         .iter()
@@ -467,13 +486,88 @@ fn main() -> Result<(), Box<dyn Error>> {
         .collect();
 
     let unweighted_accuracy = calculate_accuracy(&knn_manhattan, &test_data);
-    let unweighted_train_f1 = calculate_f1_score(&train_data, &train_predictions);
-    let unweighted_test_f1 = calculate_f1_score(&test_data, &test_predictions);
+    let unweighted_train_f1 = f1_score(&train_data, &train_predictions);
+    let unweighted_test_f1 = f1_score(&test_data, &test_predictions);
 
     println!("unweighted:");
     println!("accuracy: {unweighted_accuracy}, train f1 score: {unweighted_train_f1}, test f1 score: {unweighted_test_f1}");
 
-    knn_manhattan.fit(train_data.clone(), Some(weights));
+    let mut drift_detector = DriftDetector::from_model(&knn_manhattan);
+    for data in &test_data {
+        drift_detector.observe(&knn_manhattan, &data.features);
+    }
+    let drift = drift_detector.report();
+    println!(
+        "test drift: max feature psi {:.3}, far fraction {:.3}",
+        drift.feature_psi.iter().copied().fold(0.0, f64::max),
+        drift.far_fraction
+    );
+
+    let mut feature_importances: Vec<(usize, f64)> = knn_manhattan
+        .permutation_importance(&test_data, IMPORTANCE_SEED)
+        .into_iter()
+        .enumerate()
+        .collect();
+    feature_importances.sort_by(|first, second| second.1.total_cmp(&first.1));
+
+    println!("permutation importance:");
+    for (feature, importance) in feature_importances.iter().take(TOP_FEATURES) {
+        println!("\tfeature {feature}: {importance:.3}");
+    }
+
+    let (probabilities, labels): (Vec<Vec<f64>>, Vec<Diagnosis>) = test_data
+        .iter()
+        .filter_map(|data| {
+            let probabilities = knn_manhattan.predict_proba_dense(&data.features).ok()?;
+
+            Some((probabilities, data.label))
+        })
+        .unzip();
+    let classes = knn_manhattan.classes();
+
+    plot_reliability_diagram(
+        &reliability_diagram(&probabilities, classes, &labels, CALIBRATION_BINS),
+        CALIBRATION_PLOT_FILENAME,
+    )?;
+    println!("calibration plot saved to {CALIBRATION_PLOT_FILENAME}");
+
+    let roc = roc_curve(&labels, &probabilities, classes);
+    let roc_auc = auc(&roc);
+    plot_roc_curve(&roc, roc_auc, ROC_PLOT_FILENAME)?;
+    println!("auc: {roc_auc:.3}, roc curve saved to {ROC_PLOT_FILENAME}");
+
+    // regressing the 0/1 malignant indicator shows where the kernel average
+    // is least certain
+    let malignant_indicator = |data: &Data<Diagnosis, DIMENSIONS>| {
+        if data.label == Diagnosis::Malignant {
+            1.0
+        } else {
+            0.0
+        }
+    };
+    let mut regressor: KnnRegressor<Manhattan, DIMENSIONS> = KnnRegressor::with_params(
+        best_hyperparameters.k,
+        best_hyperparameters.radius,
+        &best_hyperparameters.window,
+        Arc::clone(&best_hyperparameters.kernel),
+    )?;
+    regressor.fit(
+        train_data.iter().map(|data| data.features).collect(),
+        train_data.iter().map(malignant_indicator).collect(),
+        None,
+    )?;
+    let (actual, predicted): (Vec<f64>, Vec<f64>) = test_data
+        .iter()
+        .filter_map(|data| {
+            let predicted = regressor.predict_value(&data.features).ok()?;
+
+            Some((malignant_indicator(data), predicted))
+        })
+        .unzip();
+    plot_residuals(&actual, &predicted, RESIDUALS_PLOT_FILENAME)?;
+    println!("residuals saved to {RESIDUALS_PLOT_FILENAME}");
+
+    knn_manhattan.fit(train_data.clone(), Some(weights.clone()), true)?;
 
     let train_predictions: Vec<_> = train_data
         .iter()
@@ -493,11 +587,193 @@ fn main() -> Result<(), Box<dyn Error>> {
         .collect();
 
     let weighted_accuracy = calculate_accuracy(&knn_manhattan, &test_data);
-    let weighted_train_f1 = calculate_f1_score(&train_data, &train_predictions);
-    let weighted_test_f1 = calculate_f1_score(&test_data, &test_predictions);
+    let weighted_train_f1 = f1_score(&train_data, &train_predictions);
+    let weighted_test_f1 = f1_score(&test_data, &test_predictions);
 
     println!("weighted:");
     println!("accuracy: {weighted_accuracy}, train f1 score: {weighted_train_f1}, test f1 score: {weighted_test_f1}");
 
+    // drop the points lowess found to be misclassified. Normalizing divides
+    // by the mean weight, so the threshold is applied to the raw lowess
+    // weights, where a correctly predicted point weighs `kernel(0)`
+    knn_manhattan.fit(train_data.clone(), Some(weights), false)?;
+    knn_manhattan.prune_by_weight(best_hyperparameters.kernel.eval(0.0));
+
+    let pruned_accuracy = calculate_accuracy(&knn_manhattan, &test_data);
+
+    println!("pruned:");
+    println!(
+        "accuracy: {pruned_accuracy}, kept {} of {} train points",
+        knn_manhattan.len(),
+        train_data.len()
+    );
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use knn::search::best_candidate;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    fn point(position: f64, label: Diagnosis) -> Data<Diagnosis, DIMENSIONS> {
+        let mut features = [0.0; DIMENSIONS];
+        features[0] = position;
+
+        Data { features, label }
+    }
+
+    #[test]
+    fn candidates_are_evaluated_with_their_vote_weighting() {
+        let train_data = [
+            point(0.1, Diagnosis::Malignant),
+            point(1.0, Diagnosis::Benign),
+            point(1.2, Diagnosis::Benign),
+        ];
+        let validation_data = [point(0.0, Diagnosis::Malignant)];
+        let candidate = |voting| Hyperparameters {
+            k: 3,
+            radius: 1.0,
+            window: WindowType::Unfixed,
+            kernel: Arc::new(Uniform),
+            voting,
+            metric: "manhattan".to_string(),
+        };
+
+        let evaluations = evaluate_candidates(
+            &[
+                candidate(VoteWeighting::Uniform),
+                candidate(VoteWeighting::InverseDistance { epsilon: 1e-9 }),
+            ],
+            &train_data,
+            &validation_data,
+        )
+        .unwrap();
+        assert_eq!(
+            evaluations
+                .iter()
+                .map(|evaluation| evaluation.accuracy)
+                .collect::<Vec<_>>(),
+            [0.0, 100.0]
+        );
+        // k = 3 of three training points, the kd-tree has to look at all
+        assert!(evaluations
+            .iter()
+            .all(|evaluation| (evaluation.mean_points_examined - 3.0).abs() < 1e-12));
+
+        assert!(evaluate_candidates(
+            &[candidate(VoteWeighting::InverseDistance { epsilon: 0.0 })],
+            &train_data,
+            &validation_data,
+        )
+        .is_err());
+    }
+
+    /// One freshly built and fitted model per candidate, one at a time
+    fn evaluate_sequentially(
+        candidates: &[Hyperparameters],
+        train_data: &[Data<Diagnosis, DIMENSIONS>],
+        validation_data: &[Data<Diagnosis, DIMENSIONS>],
+    ) -> Vec<Evaluation> {
+        fn evaluate<M: DistanceMetric<f64, DIMENSIONS> + MetricKind>(
+            hyperparameters: &Hyperparameters,
+            train_data: &[Data<Diagnosis, DIMENSIONS>],
+            validation_data: &[Data<Diagnosis, DIMENSIONS>],
+        ) -> Evaluation {
+            let mut knn: Knn<M, Diagnosis, DIMENSIONS> = KnnBuilder::new()
+                .k(hyperparameters.k)
+                .radius(hyperparameters.radius)
+                .window(hyperparameters.window)
+                .kernel(Arc::clone(&hyperparameters.kernel))
+                .voting(hyperparameters.voting)
+                .build()
+                .unwrap();
+            knn.fit(train_data.to_vec(), None, false).unwrap();
+            knn.set_instrumented(true);
+
+            Evaluation {
+                accuracy: calculate_accuracy(&knn, validation_data),
+                mean_points_examined: knn.query_stats().mean_points_examined(),
+            }
+        }
+
+        candidates
+            .iter()
+            .map(|hyperparameters| match hyperparameters.metric.as_str() {
+                "manhattan" => evaluate::<Manhattan>(hyperparameters, train_data, validation_data),
+                "squared euclidean" => {
+                    evaluate::<SquaredEuclidean>(hyperparameters, train_data, validation_data)
+                }
+                _ => evaluate::<Chebyshev>(hyperparameters, train_data, validation_data),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parallel_search_matches_a_sequential_one() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut random_point = || {
+            let position = rng.gen_range(-1.0..1.0);
+            let label = if position + rng.gen_range(-0.3..0.3) > 0.0 {
+                Diagnosis::Malignant
+            } else {
+                Diagnosis::Benign
+            };
+            let mut data_point = point(position, label);
+            data_point.features[1] = rng.gen_range(-1.0..1.0);
+
+            data_point
+        };
+        let train_data: Vec<_> = (0..60).map(|_| random_point()).collect();
+        let validation_data: Vec<_> = (0..30).map(|_| random_point()).collect();
+
+        let mut candidates = Vec::new();
+        for k in 1..8 {
+            for radius in [0.2, 0.5, 1.0] {
+                for window in [WindowType::Fixed, WindowType::Unfixed] {
+                    for (kernel, voting) in [
+                        (Arc::new(Uniform) as Arc<dyn Kernel>, VoteWeighting::Kernel),
+                        (Arc::new(Epanechnikov), VoteWeighting::Kernel),
+                        (Arc::new(Gaussian), VoteWeighting::Kernel),
+                        (
+                            Arc::new(Uniform),
+                            VoteWeighting::InverseDistance { epsilon: 1e-9 },
+                        ),
+                    ] {
+                        for metric in ["manhattan", "squared euclidean", "chebyshev"] {
+                            candidates.push(Hyperparameters {
+                                k,
+                                radius,
+                                window,
+                                kernel: Arc::clone(&kernel),
+                                voting,
+                                metric: metric.to_string(),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        let parallel = evaluate_candidates(&candidates, &train_data, &validation_data).unwrap();
+        let sequential = evaluate_sequentially(&candidates, &train_data, &validation_data);
+
+        assert_eq!(parallel, sequential);
+        let accuracies = |evaluations: &[Evaluation]| -> Vec<f64> {
+            evaluations
+                .iter()
+                .map(|evaluation| evaluation.accuracy)
+                .collect()
+        };
+        assert_eq!(
+            best_candidate(&accuracies(&parallel)),
+            best_candidate(&accuracies(&sequential))
+        );
+        assert_eq!(
+            evaluate_candidates(&candidates, &train_data, &validation_data).unwrap(),
+            parallel
+        );
+    }
+}
@@ -0,0 +1,629 @@
+use std::collections::BTreeMap;
+
+use kiddo::distance_metric::DistanceMetric;
+
+use crate::{
+    distance_metric::MetricKind,
+    knn::{Data, Knn, Label, Scalar},
+    parse::breast_cancer::Diagnosis,
+};
+
+/// Binary confusion counts with `Malignant` as the positive class
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ConfusionMatrix {
+    pub true_positive: usize,
+    pub false_positive: usize,
+    pub true_negative: usize,
+    pub false_negative: usize,
+}
+
+impl ConfusionMatrix {
+    #[must_use]
+    pub fn from_predictions(actuals: &[Diagnosis], predicted: &[Diagnosis]) -> Self {
+        let mut matrix = Self::default();
+
+        for (actual, predicted) in actuals.iter().zip(predicted) {
+            match (actual, predicted) {
+                (Diagnosis::Malignant, Diagnosis::Malignant) => matrix.true_positive += 1,
+                (Diagnosis::Benign, Diagnosis::Malignant) => matrix.false_positive += 1,
+                (Diagnosis::Benign, Diagnosis::Benign) => matrix.true_negative += 1,
+                (Diagnosis::Malignant, Diagnosis::Benign) => matrix.false_negative += 1,
+            }
+        }
+
+        matrix
+    }
+
+    #[must_use]
+    pub fn total(&self) -> usize {
+        self.true_positive + self.false_positive + self.true_negative + self.false_negative
+    }
+
+    #[must_use]
+    pub fn accuracy(&self) -> f64 {
+        ratio(self.true_positive + self.true_negative, self.total())
+    }
+
+    #[must_use]
+    pub fn precision(&self) -> f64 {
+        ratio(self.true_positive, self.true_positive + self.false_positive)
+    }
+
+    #[must_use]
+    pub fn recall(&self) -> f64 {
+        ratio(self.true_positive, self.true_positive + self.false_negative)
+    }
+
+    #[must_use]
+    pub fn f1(&self) -> f64 {
+        let precision = self.precision();
+        let recall = self.recall();
+
+        if precision + recall > 0.0 {
+            2.0 * (precision * recall) / (precision + recall)
+        } else {
+            0.0
+        }
+    }
+
+    /// Agreement between predictions and actual labels corrected for the
+    /// agreement expected by chance from the class frequencies. 0.0 when
+    /// chance agreement is already certain
+    #[must_use]
+    pub fn cohens_kappa(&self) -> f64 {
+        let total = self.total() as f64;
+        if total == 0.0 {
+            return 0.0;
+        }
+
+        let predicted_positive = (self.true_positive + self.false_positive) as f64;
+        let actual_positive = (self.true_positive + self.false_negative) as f64;
+        let predicted_negative = (self.true_negative + self.false_negative) as f64;
+        let actual_negative = (self.true_negative + self.false_positive) as f64;
+
+        let observed = self.accuracy();
+        let expected = (predicted_positive * actual_positive
+            + predicted_negative * actual_negative)
+            / (total * total);
+
+        if expected < 1.0 {
+            (observed - expected) / (1.0 - expected)
+        } else {
+            0.0
+        }
+    }
+}
+
+pub(crate) fn ratio(numerator: usize, denominator: usize) -> f64 {
+    if denominator > 0 {
+        numerator as f64 / denominator as f64
+    } else {
+        0.0
+    }
+}
+
+#[must_use]
+pub fn f1_score<const D: usize, T: Scalar>(
+    data: &[Data<Diagnosis, D, T>],
+    predictions: &[Diagnosis],
+) -> f64 {
+    let actuals: Vec<Diagnosis> = data.iter().map(|data_point| data_point.label).collect();
+
+    ConfusionMatrix::from_predictions(&actuals, predictions).f1()
+}
+
+/// True positive, false positive and false negative counts of every class
+/// among the actual and predicted labels
+fn per_class_counts<L: Label>(actuals: &[L], predicted: &[L]) -> BTreeMap<L, [usize; 3]> {
+    let mut counts: BTreeMap<L, [usize; 3]> = BTreeMap::new();
+
+    for (&actual, &predicted) in actuals.iter().zip(predicted) {
+        if actual == predicted {
+            counts.entry(actual).or_default()[0] += 1;
+        } else {
+            counts.entry(predicted).or_default()[1] += 1;
+            counts.entry(actual).or_default()[2] += 1;
+        }
+    }
+
+    counts
+}
+
+fn f1_of(true_positive: usize, false_positive: usize, false_negative: usize) -> f64 {
+    ConfusionMatrix {
+        true_positive,
+        false_positive,
+        false_negative,
+        ..ConfusionMatrix::default()
+    }
+    .f1()
+}
+
+/// Unweighted mean of the per-class F1 scores, so small classes count as
+/// much as large ones
+#[must_use]
+pub fn f1_macro<L: Label>(actuals: &[L], predicted: &[L]) -> f64 {
+    let counts = per_class_counts(actuals, predicted);
+    if counts.is_empty() {
+        return 0.0;
+    }
+
+    counts
+        .values()
+        .map(|&[true_positive, false_positive, false_negative]| {
+            f1_of(true_positive, false_positive, false_negative)
+        })
+        .sum::<f64>()
+        / counts.len() as f64
+}
+
+/// F1 of the counts pooled over every class, dominated by the large classes.
+/// Equals the accuracy when every point has exactly one label
+#[must_use]
+pub fn f1_micro<L: Label>(actuals: &[L], predicted: &[L]) -> f64 {
+    let [true_positive, false_positive, false_negative] = per_class_counts(actuals, predicted)
+        .values()
+        .fold([0; 3], |total, counts| {
+            [
+                total[0] + counts[0],
+                total[1] + counts[1],
+                total[2] + counts[2],
+            ]
+        });
+
+    f1_of(true_positive, false_positive, false_negative)
+}
+
+#[must_use]
+pub fn cohens_kappa<const D: usize, T: Scalar>(
+    data: &[Data<Diagnosis, D, T>],
+    predictions: &[Diagnosis],
+) -> f64 {
+    let actuals: Vec<Diagnosis> = data.iter().map(|data_point| data_point.label).collect();
+
+    ConfusionMatrix::from_predictions(&actuals, predictions).cohens_kappa()
+}
+
+/// Percentage of `test_data` predicted correctly, see [`Knn::score`]
+#[must_use]
+pub fn calculate_accuracy<M, L, const D: usize, T>(
+    knn: &Knn<M, L, D, T>,
+    test_data: &[Data<L, D, T>],
+) -> f64
+where
+    M: DistanceMetric<T, D> + MetricKind,
+    L: Label,
+    T: Scalar,
+{
+    knn.score(test_data) * 100.0
+}
+
+/// Fraction of points both models predict the same class for, regardless of
+/// the true label. A point either model can't predict counts as disagreement
+#[must_use]
+pub fn prediction_agreement<M1, M2, L, const D: usize, T>(
+    a: &Knn<M1, L, D, T>,
+    b: &Knn<M2, L, D, T>,
+    data: &[Data<L, D, T>],
+) -> f64
+where
+    M1: DistanceMetric<T, D> + MetricKind,
+    M2: DistanceMetric<T, D> + MetricKind,
+    L: Label,
+    T: Scalar,
+{
+    let features: Vec<[T; D]> = data.iter().map(|data_point| data_point.features).collect();
+
+    let agreements = a
+        .predict_batch(&features)
+        .into_iter()
+        .zip(b.predict_batch(&features))
+        .filter(|(first, second)| match (first, second) {
+            (Ok(first), Ok(second)) => first == second,
+            _ => false,
+        })
+        .count();
+
+    ratio(agreements, data.len())
+}
+
+/// The `Malignant` column of dense probability rows laid out in the order of
+/// `classes`, all zeros when the model never saw `Malignant`
+fn malignant_column(proba: &[Vec<f64>], classes: &[Diagnosis]) -> Vec<f64> {
+    let column = classes
+        .iter()
+        .position(|&class| class == Diagnosis::Malignant);
+
+    proba
+        .iter()
+        .map(|row| {
+            column
+                .and_then(|column| row.get(column))
+                .copied()
+                .unwrap_or(0.0)
+        })
+        .collect()
+}
+
+/// Mean predicted `Malignant` probability and observed `Malignant`
+/// frequency of every non-empty bin out of `bins` equal-width bins over
+/// [0, 1]. A calibrated model gives points near the diagonal. `proba` holds
+/// [`Knn::predict_proba_dense`] rows in the order of `classes`
+#[must_use]
+pub fn reliability_diagram(
+    proba: &[Vec<f64>],
+    classes: &[Diagnosis],
+    labels: &[Diagnosis],
+    bins: usize,
+) -> Vec<(f64, f64)> {
+    if bins == 0 {
+        return Vec::new();
+    }
+
+    let mut probability_sums = vec![0.0; bins];
+    let mut positives = vec![0_usize; bins];
+    let mut counts = vec![0_usize; bins];
+
+    for (probability, label) in malignant_column(proba, classes).into_iter().zip(labels) {
+        #[allow(clippy::cast_possible_truncation)]
+        #[allow(clippy::cast_sign_loss)]
+        let bin = ((probability.clamp(0.0, 1.0) * bins as f64) as usize).min(bins - 1);
+
+        probability_sums[bin] += probability;
+        counts[bin] += 1;
+        if *label == Diagnosis::Malignant {
+            positives[bin] += 1;
+        }
+    }
+
+    (0..bins)
+        .filter(|&bin| counts[bin] > 0)
+        .map(|bin| {
+            (
+                probability_sums[bin] / counts[bin] as f64,
+                ratio(positives[bin], counts[bin]),
+            )
+        })
+        .collect()
+}
+
+/// (false positive rate, true positive rate) of predicting `Malignant` for
+/// every probability at or above each threshold, from the highest down to
+/// the lowest. Starts at (0, 0) and ends at (1, 1), equal probabilities share
+/// one point. `proba` holds [`Knn::predict_proba_dense`] rows in the order of
+/// `classes`
+#[must_use]
+pub fn roc_curve(
+    actuals: &[Diagnosis],
+    proba: &[Vec<f64>],
+    classes: &[Diagnosis],
+) -> Vec<(f64, f64)> {
+    let mut scored: Vec<(f64, Diagnosis)> = malignant_column(proba, classes)
+        .into_iter()
+        .zip(actuals.iter().copied())
+        .collect();
+    scored.sort_by(|first, second| second.0.total_cmp(&first.0));
+
+    let positives = scored
+        .iter()
+        .filter(|(_, actual)| *actual == Diagnosis::Malignant)
+        .count();
+    let negatives = scored.len() - positives;
+
+    let mut points = vec![(0.0, 0.0)];
+    let (mut true_positives, mut false_positives) = (0, 0);
+
+    for (index, &(score, actual)) in scored.iter().enumerate() {
+        match actual {
+            Diagnosis::Malignant => true_positives += 1,
+            Diagnosis::Benign => false_positives += 1,
+        }
+
+        let last_of_threshold = scored
+            .get(index + 1)
+            .is_none_or(|(next_score, _)| next_score.total_cmp(&score).is_ne());
+        if last_of_threshold {
+            points.push((
+                ratio(false_positives, negatives),
+                ratio(true_positives, positives),
+            ));
+        }
+    }
+
+    if points.last() != Some(&(1.0, 1.0)) {
+        points.push((1.0, 1.0));
+    }
+
+    points
+}
+
+/// Area under a curve of points sorted by x, by the trapezoidal rule
+#[must_use]
+pub fn auc(points: &[(f64, f64)]) -> f64 {
+    points
+        .windows(2)
+        .map(|pair| (pair[1].0 - pair[0].0) * (pair[0].1 + pair[1].1) / 2.0)
+        .sum()
+}
+
+/// Linearly interpolated `q`-quantile of already sorted values
+#[must_use]
+pub fn quantile(sorted: &[f64], q: f64) -> f64 {
+    if sorted.is_empty() {
+        return f64::NAN;
+    }
+
+    let position = q.clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_sign_loss)]
+    let lower = position.floor() as usize;
+    let upper = (lower + 1).min(sorted.len() - 1);
+    let fraction = position - lower as f64;
+
+    sorted[lower] + (sorted[upper] - sorted[lower]) * fraction
+}
+
+#[cfg(test)]
+mod tests {
+    use kiddo::Manhattan;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::{kernel::Uniform, knn::WindowType};
+
+    #[test]
+    fn macro_f1_falls_below_micro_f1_on_imbalanced_classes() {
+        let mut actuals = vec![0; 90];
+        actuals.extend([1; 5]);
+        actuals.extend([2; 5]);
+        let mut predicted = vec![0; 90];
+        predicted.extend([1, 0, 0, 0, 0]);
+        predicted.extend([2, 0, 0, 0, 0]);
+
+        let (by_class, pooled) = (
+            f1_macro(&actuals, &predicted),
+            f1_micro(&actuals, &predicted),
+        );
+
+        // micro averaging of single-label predictions is the accuracy
+        assert!((pooled - 0.92).abs() < 1e-9);
+        assert!(by_class < pooled - 0.3);
+    }
+
+    #[test]
+    fn confusion_matrices_count_each_outcome() {
+        use Diagnosis::{Benign, Malignant};
+
+        let matrix = ConfusionMatrix::from_predictions(
+            &[Malignant, Malignant, Malignant, Benign, Benign],
+            &[Malignant, Malignant, Benign, Malignant, Benign],
+        );
+
+        assert_eq!(
+            matrix,
+            ConfusionMatrix {
+                true_positive: 2,
+                false_positive: 1,
+                true_negative: 1,
+                false_negative: 1,
+            }
+        );
+        assert!((matrix.accuracy() - 0.6).abs() < 1e-12);
+        assert!((matrix.precision() - 2.0 / 3.0).abs() < 1e-12);
+        assert!((matrix.recall() - 2.0 / 3.0).abs() < 1e-12);
+        assert!((matrix.f1() - 2.0 / 3.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn one_sided_predictions_score_zero_instead_of_dividing_by_zero() {
+        use Diagnosis::{Benign, Malignant};
+        let actuals = [Malignant, Benign, Benign, Benign];
+
+        // nothing predicted positive, so precision has no denominator
+        let all_benign = ConfusionMatrix::from_predictions(&actuals, &[Benign; 4]);
+        assert!(all_benign.precision().abs() < f64::EPSILON);
+        assert!(all_benign.recall().abs() < f64::EPSILON);
+        assert!(all_benign.f1().abs() < f64::EPSILON);
+        assert!((all_benign.accuracy() - 0.75).abs() < 1e-12);
+
+        let all_malignant = ConfusionMatrix::from_predictions(&actuals, &[Malignant; 4]);
+        assert!((all_malignant.precision() - 0.25).abs() < 1e-12);
+        assert!((all_malignant.recall() - 1.0).abs() < 1e-12);
+        assert!((all_malignant.f1() - 0.4).abs() < 1e-12);
+
+        // nothing actually positive, so recall has no denominator
+        let no_positives = ConfusionMatrix::from_predictions(&[Benign; 2], &[Malignant; 2]);
+        assert!(no_positives.recall().abs() < f64::EPSILON);
+        assert!(no_positives.f1().abs() < f64::EPSILON);
+
+        assert_eq!(ConfusionMatrix::from_predictions(&[], &[]).total(), 0);
+        assert!(ConfusionMatrix::default().accuracy().abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn always_predicting_the_majority_has_no_kappa_despite_its_accuracy() {
+        let data: Vec<Data<Diagnosis, 1>> = (0..100)
+            .map(|index| Data {
+                features: [f64::from(index)],
+                label: if index < 90 {
+                    Diagnosis::Benign
+                } else {
+                    Diagnosis::Malignant
+                },
+            })
+            .collect();
+        let labels: Vec<Diagnosis> = data.iter().map(|data_point| data_point.label).collect();
+        let majority = [Diagnosis::Benign; 100];
+
+        assert!(
+            (ConfusionMatrix::from_predictions(&labels, &majority).accuracy() - 0.9).abs() < 1e-12
+        );
+        assert!(cohens_kappa(&data, &majority).abs() < 1e-12);
+        assert!((cohens_kappa(&data, &labels) - 1.0).abs() < 1e-12);
+
+        // one of the ten malignant points found, no false alarms
+        let mut one_found = majority;
+        one_found[90] = Diagnosis::Malignant;
+        let kappa = cohens_kappa(&data, &one_found);
+        assert!(kappa > 0.0 && kappa < 0.2, "{kappa}");
+    }
+
+    fn alternating(len: usize) -> Vec<Diagnosis> {
+        (0..len)
+            .map(|index| {
+                if index % 2 == 0 {
+                    Diagnosis::Malignant
+                } else {
+                    Diagnosis::Benign
+                }
+            })
+            .collect()
+    }
+
+    const CLASSES: [Diagnosis; 2] = [Diagnosis::Malignant, Diagnosis::Benign];
+
+    fn dense(malignant: f64) -> Vec<f64> {
+        vec![malignant, 1.0 - malignant]
+    }
+
+    #[test]
+    fn separated_scores_have_an_auc_of_one() {
+        let actuals = alternating(1000);
+        let separated: Vec<Vec<f64>> = actuals
+            .iter()
+            .enumerate()
+            .map(|(index, actual)| match actual {
+                Diagnosis::Malignant => dense(0.6 + index as f64 * 1e-4),
+                Diagnosis::Benign => dense(0.4 - index as f64 * 1e-4),
+            })
+            .collect();
+
+        assert!((auc(&roc_curve(&actuals, &separated, &CLASSES)) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn random_scores_have_an_auc_near_one_half() {
+        let actuals = alternating(1000);
+        let mut rng = StdRng::seed_from_u64(0);
+        let random: Vec<Vec<f64>> = actuals.iter().map(|_| dense(rng.gen())).collect();
+
+        assert!((auc(&roc_curve(&actuals, &random, &CLASSES)) - 0.5).abs() < 0.05);
+    }
+
+    #[test]
+    fn calibrated_scores_lie_near_the_diagonal() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let (proba, labels): (Vec<Vec<f64>>, Vec<Diagnosis>) = (0..20_000)
+            .map(|_| {
+                let malignant: f64 = rng.gen();
+                let label = if rng.gen::<f64>() < malignant {
+                    Diagnosis::Malignant
+                } else {
+                    Diagnosis::Benign
+                };
+
+                (dense(malignant), label)
+            })
+            .unzip();
+
+        let points = reliability_diagram(&proba, &CLASSES, &labels, 10);
+
+        assert_eq!(points.len(), 10);
+        for (predicted, observed) in points {
+            assert!(
+                (predicted - observed).abs() < 0.05,
+                "{predicted} {observed}"
+            );
+        }
+        assert!(reliability_diagram(&proba, &CLASSES, &labels, 0).is_empty());
+    }
+
+    #[test]
+    fn empty_bins_are_left_out_of_the_diagram() {
+        let proba = [dense(0.1), dense(0.15), dense(1.0)];
+        let labels = [
+            Diagnosis::Benign,
+            Diagnosis::Malignant,
+            Diagnosis::Malignant,
+        ];
+
+        let points = reliability_diagram(&proba, &CLASSES, &labels, 4);
+
+        // a probability of exactly 1 falls into the last bin
+        assert_eq!(points.len(), 2);
+        assert!((points[0].0 - 0.125).abs() < 1e-12 && (points[0].1 - 0.5).abs() < 1e-12);
+        assert!((points[1].0 - 1.0).abs() < 1e-12 && (points[1].1 - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn identical_models_always_agree_and_different_ones_less() {
+        use crate::distance_metric::Chebyshev;
+
+        // from the origin (3, 3) is nearer by Chebyshev, (5, 0) by Manhattan
+        let data: Vec<Data<Diagnosis, 2>> = [
+            ([3.0, 3.0], Diagnosis::Malignant),
+            ([5.0, 0.0], Diagnosis::Benign),
+        ]
+        .into_iter()
+        .map(|(features, label)| Data { features, label })
+        .collect();
+        let queries: Vec<Data<Diagnosis, 2>> = [[0.0, 0.0], [5.2, 0.0], [3.0, 3.2]]
+            .into_iter()
+            .map(|features| Data {
+                features,
+                label: Diagnosis::Benign,
+            })
+            .collect();
+
+        let mut manhattan: Knn<Manhattan, Diagnosis, 2> =
+            Knn::with_params(1, 0.0, &WindowType::Unfixed, Uniform).unwrap();
+        manhattan.fit(data.clone(), None, false).unwrap();
+        let mut chebyshev: Knn<Chebyshev, Diagnosis, 2> =
+            Knn::with_params(1, 0.0, &WindowType::Unfixed, Uniform).unwrap();
+        chebyshev.fit(data, None, false).unwrap();
+
+        assert!(
+            (prediction_agreement(&manhattan, &manhattan.clone(), &queries) - 1.0).abs() < 1e-12
+        );
+        assert!((prediction_agreement(&manhattan, &chebyshev, &queries) - 2.0 / 3.0).abs() < 1e-12);
+
+        // a model that can't predict agrees with nothing
+        let unfitted: Knn<Manhattan, Diagnosis, 2> =
+            Knn::with_params(1, 0.0, &WindowType::Unfixed, Uniform).unwrap();
+        assert!(prediction_agreement(&manhattan, &unfitted, &queries).abs() < f64::EPSILON);
+        assert!(prediction_agreement(&manhattan, &chebyshev, &[]).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn score_matches_calculate_accuracy() {
+        let point = |x: f64, label| Data {
+            features: [x, 0.0],
+            label,
+        };
+        let mut knn: Knn<Manhattan, Diagnosis, 2> =
+            Knn::with_params(1, 1.0, &WindowType::Fixed, Uniform).unwrap();
+        knn.fit(
+            vec![
+                point(0.0, Diagnosis::Benign),
+                point(10.0, Diagnosis::Malignant),
+            ],
+            None,
+            false,
+        )
+        .unwrap();
+
+        // right, wrong, and one outside every window, which counts as wrong
+        let test_data = [
+            point(0.5, Diagnosis::Benign),
+            point(9.5, Diagnosis::Benign),
+            point(10.2, Diagnosis::Malignant),
+            point(5.0, Diagnosis::Malignant),
+        ];
+
+        assert!((knn.score(&test_data) - 0.5).abs() < 1e-12);
+        assert!(
+            (calculate_accuracy(&knn, &test_data) - knn.score(&test_data) * 100.0).abs() < 1e-12
+        );
+        assert!(knn.score(&[]).abs() < f64::EPSILON);
+    }
+}
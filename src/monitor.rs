@@ -0,0 +1,271 @@
+#[cfg(feature = "serde")]
+use std::{
+    error::Error,
+    fs::File,
+    io::{BufReader, BufWriter},
+};
+
+use kiddo::distance_metric::DistanceMetric;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    distance_metric::MetricKind,
+    knn::{Knn, Label, Scalar},
+    metrics::quantile,
+};
+
+const BINS: usize = 10;
+const DISTANCE_QUANTILE: f64 = 0.99;
+const MIN_PROPORTION: f64 = 1e-4;
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DriftReport {
+    pub queries: usize,
+    /// Population stability index of every feature
+    pub feature_psi: Vec<f64>,
+    /// Fraction of queries farther from their nearest neighbour than the
+    /// training 99th percentile
+    pub far_fraction: f64,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DriftDetector {
+    bin_edges: Vec<Vec<f64>>,
+    expected: Vec<Vec<f64>>,
+    observed: Vec<Vec<usize>>,
+    distance_threshold: f64,
+    queries: usize,
+    far_queries: usize,
+}
+
+impl DriftDetector {
+    /// Bins every feature of the live training points. On a whitened model
+    /// the bins are in whitened space, [`DriftDetector::observe`] whitens
+    /// queries to match
+    pub fn from_model<M, L, const D: usize, T>(model: &Knn<M, L, D, T>) -> Self
+    where
+        M: DistanceMetric<T, D> + MetricKind,
+        L: Label,
+        T: Scalar,
+    {
+        let mut bin_edges = Vec::with_capacity(D);
+        let mut expected = Vec::with_capacity(D);
+
+        for feature in 0..D {
+            let mut values: Vec<f64> = model
+                .iter()
+                .map(|data_point| data_point.features[feature].into())
+                .collect();
+            values.sort_by(f64::total_cmp);
+
+            let edges: Vec<f64> = (1..BINS)
+                .map(|bin| quantile(&values, bin as f64 / BINS as f64))
+                .collect();
+
+            let mut counts = vec![0; BINS];
+            for &value in &values {
+                counts[bin_of(&edges, value)] += 1;
+            }
+
+            expected.push(proportions(&counts, values.len()));
+            bin_edges.push(edges);
+        }
+
+        let mut distances = model.k_distances(1);
+        distances.sort_by(f64::total_cmp);
+
+        Self {
+            bin_edges,
+            expected,
+            observed: vec![vec![0; BINS]; D],
+            distance_threshold: quantile(&distances, DISTANCE_QUANTILE),
+            queries: 0,
+            far_queries: 0,
+        }
+    }
+
+    pub fn observe<M, L, const D: usize, T>(&mut self, model: &Knn<M, L, D, T>, x: &[T; D])
+    where
+        M: DistanceMetric<T, D> + MetricKind,
+        L: Label,
+        T: Scalar,
+    {
+        let whitened = model.whitened(x);
+        for ((edges, counts), &value) in
+            self.bin_edges.iter().zip(&mut self.observed).zip(&whitened)
+        {
+            counts[bin_of(edges, value.into())] += 1;
+        }
+
+        if model
+            .nearest_distance(x)
+            .is_some_and(|distance| distance > self.distance_threshold)
+        {
+            self.far_queries += 1;
+        }
+
+        self.queries += 1;
+    }
+
+    pub fn report(&self) -> DriftReport {
+        let feature_psi = self
+            .expected
+            .iter()
+            .zip(&self.observed)
+            .map(|(expected, observed)| {
+                expected
+                    .iter()
+                    .zip(proportions(observed, self.queries))
+                    .map(|(&expected, actual)| {
+                        let expected = expected.max(MIN_PROPORTION);
+                        let actual = actual.max(MIN_PROPORTION);
+                        (actual - expected) * (actual / expected).ln()
+                    })
+                    .sum()
+            })
+            .collect();
+
+        let far_fraction = if self.queries > 0 {
+            self.far_queries as f64 / self.queries as f64
+        } else {
+            0.0
+        };
+
+        DriftReport {
+            queries: self.queries,
+            feature_psi,
+            far_fraction,
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        serde_json::to_writer(BufWriter::new(File::create(path)?), self)?;
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+    }
+}
+
+fn bin_of(edges: &[f64], value: f64) -> usize {
+    edges.partition_point(|&edge| edge < value)
+}
+
+fn proportions(counts: &[usize], total: usize) -> Vec<f64> {
+    counts
+        .iter()
+        .map(|&count| {
+            if total > 0 {
+                count as f64 / total as f64
+            } else {
+                0.0
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use kiddo::SquaredEuclidean;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::{
+        kernel::Uniform,
+        knn::{Data, WindowType},
+        parse::breast_cancer::Diagnosis,
+    };
+
+    /// PSI above this is usually read as a significant shift
+    const PSI_ALARM: f64 = 0.25;
+
+    fn points(seed: u64, count: usize, shift: f64) -> Vec<[f64; 2]> {
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        (0..count)
+            .map(|_| {
+                let (a_val, b_val): (f64, f64) = (rng.gen(), rng.gen());
+                [a_val + shift, 2.0 * a_val + 0.5 * b_val + shift]
+            })
+            .collect()
+    }
+
+    fn model(whiten: bool) -> Knn<SquaredEuclidean, Diagnosis, 2> {
+        let data: Vec<Data<Diagnosis, 2>> = points(1, 500, 0.0)
+            .into_iter()
+            .map(|features| Data {
+                features,
+                label: Diagnosis::Benign,
+            })
+            .collect();
+        let mut knn = Knn::with_params(3, 0.0, &WindowType::Unfixed, Uniform).unwrap();
+
+        if whiten {
+            knn.with_pca_whitening(&data).unwrap();
+        } else {
+            knn.fit(data, None, false).unwrap();
+        }
+
+        knn
+    }
+
+    fn report(knn: &Knn<SquaredEuclidean, Diagnosis, 2>, shift: f64) -> DriftReport {
+        let mut detector = DriftDetector::from_model(knn);
+        for query in points(2, 500, shift) {
+            detector.observe(knn, &query);
+        }
+
+        detector.report()
+    }
+
+    #[test]
+    fn in_distribution_streams_raise_no_alarm() {
+        for whiten in [false, true] {
+            let report = report(&model(whiten), 0.0);
+
+            assert_eq!(report.queries, 500);
+            assert!(
+                report.feature_psi.iter().all(|&psi| psi < PSI_ALARM),
+                "{whiten}: {report:?}"
+            );
+            assert!(report.far_fraction < 0.05, "{whiten}: {report:?}");
+        }
+    }
+
+    #[test]
+    fn shifted_streams_raise_an_alarm() {
+        for whiten in [false, true] {
+            let report = report(&model(whiten), 0.5);
+
+            assert!(
+                report.feature_psi.iter().any(|&psi| psi > PSI_ALARM),
+                "{whiten}: {report:?}"
+            );
+            assert!(report.far_fraction > 0.05, "{whiten}: {report:?}");
+        }
+    }
+
+    #[test]
+    fn removed_points_are_left_out_of_the_bins() {
+        let mut knn = model(false);
+        let before = DriftDetector::from_model(&knn);
+        let far = knn.data().len();
+        knn.add(
+            Data {
+                features: [100.0, 100.0],
+                label: Diagnosis::Benign,
+            },
+            None,
+        )
+        .unwrap();
+        knn.remove(far).unwrap();
+
+        assert_eq!(DriftDetector::from_model(&knn).bin_edges, before.bin_edges);
+    }
+}
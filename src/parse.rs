@@ -1,3 +1,65 @@
 pub mod breast_cancer;
+pub mod generic;
+pub mod missing;
+pub mod normalize;
 pub mod phones;
+pub mod sniff;
 pub mod subtitles;
+
+use std::{error::Error, fmt};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    MissingField(usize),
+    UnknownDiagnosis(String),
+    UnknownOs(String),
+    UnknownGender(String),
+    UnknownSource(String),
+    /// The source is recorded as unknown (`?`) in the dataset itself
+    NoSource,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingField(index) => write!(f, "record has no field {index}"),
+            Self::UnknownDiagnosis(value) => write!(f, "unexpected diagnosis {value}"),
+            Self::UnknownOs(value) => write!(f, "unexpected os {value}"),
+            Self::UnknownGender(value) => write!(f, "unexpected gender {value}"),
+            Self::UnknownSource(value) => write!(f, "unknown source: {value}"),
+            Self::NoSource => write!(f, "no source"),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+pub(crate) fn field(record: &csv::StringRecord, index: usize) -> Result<&str, ParseError> {
+    record.get(index).ok_or(ParseError::MissingField(index))
+}
+
+/// Concatenates the raw rows of every file, checking they agree on width.
+/// Imputation and normalization are left to the caller, so they run once over
+/// the combined rows
+pub(crate) fn parse_many<T>(
+    paths: &[&str],
+    read_rows: fn(&str) -> Result<Vec<T>, Box<dyn Error>>,
+    width: fn(&T) -> usize,
+) -> Result<Vec<T>, Box<dyn Error>> {
+    let mut rows: Vec<T> = Vec::new();
+
+    for path in paths {
+        let file_rows = read_rows(path)?;
+
+        if let (Some(expected), Some(got)) = (rows.first(), file_rows.first()) {
+            let (expected, got) = (width(expected), width(got));
+            if expected != got {
+                return Err(format!("{path}: expected {expected} features, got {got}").into());
+            }
+        }
+
+        rows.extend(file_rows);
+    }
+
+    Ok(rows)
+}
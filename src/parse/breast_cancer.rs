@@ -1,7 +1,16 @@
-use csv::ReaderBuilder;
 use std::error::Error;
 use std::fs::File;
-use std::io::BufReader;
+use std::io::Read;
+
+use super::{
+    field,
+    missing::{impute_mean, missing_indicators, parse_cell},
+    normalize::z_score_columns,
+    sniff::csv_reader,
+    ParseError,
+};
+
+pub const DIMENSIONS: usize = 30;
 
 #[derive(Debug)]
 pub struct CsvEntry {
@@ -9,17 +18,18 @@ pub struct CsvEntry {
     pub values: Vec<f64>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Diagnosis {
     Malignant,
     Benign,
 }
 
-pub fn to_diagnosis(diagnosis: &str) -> Diagnosis {
+pub fn to_diagnosis(diagnosis: &str) -> Result<Diagnosis, ParseError> {
     match diagnosis {
-        "M" => Diagnosis::Malignant,
-        "B" => Diagnosis::Benign,
-        val => panic!("unexpected diagnosis {val}"),
+        "M" => Ok(Diagnosis::Malignant),
+        "B" => Ok(Diagnosis::Benign),
+        val => Err(ParseError::UnknownDiagnosis(val.to_string())),
     }
 }
 
@@ -30,58 +40,123 @@ pub fn opposite_diagnosis(target: Diagnosis) -> Diagnosis {
     }
 }
 
-pub fn z_score_normalize(data: &[f64]) -> Vec<f64> {
-    let mean = data.iter().copied().sum::<f64>() / data.len() as f64;
-    let variance = data.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / data.len() as f64;
-    let std_dev = variance.sqrt();
+pub fn parse(file_path: &str) -> Result<Vec<CsvEntry>, Box<dyn Error>> {
+    parse_reader(File::open(file_path)?)
+}
 
-    data.iter().map(|&x| (x - mean) / std_dev).collect()
+pub fn parse_reader<R: Read>(reader: R) -> Result<Vec<CsvEntry>, Box<dyn Error>> {
+    Ok(to_entries(parse_rows(reader)?))
 }
 
-pub fn parse(file_path: &str) -> Result<Vec<CsvEntry>, Box<dyn Error>> {
-    let file = File::open(file_path)?;
-    let mut reader = ReaderBuilder::new()
-        .has_headers(true)
-        .from_reader(BufReader::new(file));
+/// Like [`parse`], but appends a 0/1 feature for every column with missing
+/// cells, set where the original cell was missing. The extra features are
+/// not normalized, and the width depends on the file
+pub fn parse_with_missing_indicators(file_path: &str) -> Result<Vec<CsvEntry>, Box<dyn Error>> {
+    let rows = parse_rows(File::open(file_path)?)?;
+    let cells: Vec<Vec<Option<f64>>> = rows.iter().map(|(_, cells)| cells.clone()).collect();
+
+    let mut entries = to_entries(rows);
+    for (entry, indicators) in entries.iter_mut().zip(missing_indicators(&cells)) {
+        entry.values.extend(indicators);
+    }
 
-    let mut entries = Vec::new();
-    let mut values_list = Vec::new();
+    Ok(entries)
+}
+
+/// A diagnosis with its raw, possibly missing, feature cells
+type Row = (Diagnosis, Vec<Option<f64>>);
+
+fn parse_rows<R: Read>(reader: R) -> Result<Vec<Row>, Box<dyn Error>> {
+    let mut reader = csv_reader(reader)?;
+
+    let mut rows = Vec::new();
 
     for result in reader.records() {
         const DIAGNOSIS_FIELD_INDEX: usize = 1;
 
         let record = result?;
 
-        let diagnosis_str = record.get(DIAGNOSIS_FIELD_INDEX).unwrap();
-        let diagnosis = to_diagnosis(diagnosis_str);
-
-        let values: Vec<f64> = record
+        let diagnosis = to_diagnosis(field(&record, DIAGNOSIS_FIELD_INDEX)?)?;
+        let cells = record
             .iter()
-            .enumerate()
-            .filter_map(|(index, value)| {
-                if index <= DIAGNOSIS_FIELD_INDEX {
-                    None
-                } else {
-                    value.parse::<f64>().ok()
-                }
-            })
+            .skip(DIAGNOSIS_FIELD_INDEX + 1)
+            .map(parse_cell)
             .collect();
 
-        values_list.push(values.clone());
-
-        entries.push(CsvEntry { diagnosis, values });
+        rows.push((diagnosis, cells));
     }
 
-    let normalized_values = z_score_normalize(&values_list.concat());
+    Ok(rows)
+}
+
+fn to_entries(rows: Vec<Row>) -> Vec<CsvEntry> {
+    let (diagnoses, cells): (Vec<Diagnosis>, Vec<Vec<Option<f64>>>) = rows.into_iter().unzip();
+
+    diagnoses
+        .into_iter()
+        .zip(z_score_columns(&impute_mean(&cells)))
+        .map(|(diagnosis, values)| CsvEntry { diagnosis, values })
+        .collect()
+}
+
+/// Concatenates the raw rows of every file before imputing and normalizing,
+/// so all files share one set of column statistics
+pub fn parse_many(paths: &[&str]) -> Result<Vec<CsvEntry>, Box<dyn Error>> {
+    let rows = super::parse_many(
+        paths,
+        |path| parse_rows(File::open(path)?),
+        |(_, cells)| cells.len(),
+    )?;
+
+    Ok(to_entries(rows))
+}
 
-    let value_length = entries.first().map_or(0, |entry| entry.values.len());
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
 
-    for (entry, new_values) in entries
-        .iter_mut()
-        .zip(normalized_values.chunks(value_length))
-    {
-        entry.values = new_values.to_vec();
+    use super::*;
+
+    #[test]
+    fn inline_rows_parse_into_diagnoses_and_features() {
+        let csv = "\
+id,diagnosis,radius,texture,perimeter
+842302,M,17.99,10.38,122.8
+842517,B,13.54,14.36,87.46
+";
+
+        let entries = parse_reader(csv.as_bytes()).unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].diagnosis, Diagnosis::Malignant);
+        assert_eq!(entries[1].diagnosis, Diagnosis::Benign);
+        assert!(entries.iter().all(|entry| entry.values.len() == 3));
     }
 
-    Ok(entries)
+    #[test]
+    fn unknown_diagnoses_are_an_error() {
+        assert!(parse_reader("id,diagnosis,radius\n1,X,2.0\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn parse_many_normalizes_the_concatenated_rows() {
+        let dir = env::temp_dir().join(format!("knn-parse-many-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let low = dir.join("low.csv");
+        let high = dir.join("high.csv");
+        fs::write(&low, "id,diagnosis,radius\n1,B,1\n2,B,2\n").unwrap();
+        fs::write(&high, "id,diagnosis,radius\n3,M,9\n4,M,10\n5,M,11\n").unwrap();
+
+        let entries = parse_many(&[low.to_str().unwrap(), high.to_str().unwrap()]);
+        fs::remove_dir_all(&dir).unwrap();
+        let entries = entries.unwrap();
+
+        assert_eq!(entries.len(), 5);
+        let values: Vec<f64> = entries.iter().map(|entry| entry.values[0]).collect();
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        assert!(mean.abs() < 1e-9);
+        // normalized per file, both files would straddle zero
+        assert!(values[..2].iter().all(|&value| value < 0.0));
+        assert!(values[2..].iter().all(|&value| value > 0.0));
+    }
 }
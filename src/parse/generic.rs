@@ -0,0 +1,338 @@
+use csv::StringRecord;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::ops::Range;
+
+use super::{
+    field,
+    missing::parse_cell,
+    sniff::{csv_reader, sniff_file},
+};
+
+#[derive(Debug, Clone)]
+pub struct ColumnSpec {
+    /// Label column index in the labels file
+    pub label_column: usize,
+    /// Feature column indices in the features file
+    pub feature_columns: Vec<Range<usize>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JoinError {
+    MissingColumn {
+        file: String,
+        column: String,
+    },
+    DuplicateIds {
+        file: String,
+        ids: Vec<String>,
+    },
+    UnmatchedIds {
+        without_label: Vec<String>,
+        without_features: Vec<String>,
+    },
+}
+
+impl fmt::Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingColumn { file, column } => {
+                write!(f, "{file}: no column named {column}")
+            }
+            Self::DuplicateIds { file, ids } => write!(f, "{file}: duplicate ids {ids:?}"),
+            Self::UnmatchedIds {
+                without_label,
+                without_features,
+            } => write!(
+                f,
+                "ids without a label: {without_label:?}, ids without features: {without_features:?}"
+            ),
+        }
+    }
+}
+
+impl Error for JoinError {}
+
+/// Hash-joins a features CSV with a labels CSV on the `join_column` header,
+/// keeping the row order of the features file
+pub fn parse_joined(
+    features_path: &str,
+    labels_path: &str,
+    join_column: &str,
+    spec: &ColumnSpec,
+) -> Result<Vec<(String, Vec<f64>)>, Box<dyn Error>> {
+    let feature_rows = read_keyed(features_path, join_column)?;
+    let label_rows = read_keyed(labels_path, join_column)?;
+
+    let labels: HashMap<&str, &StringRecord> = label_rows
+        .iter()
+        .map(|(id, record)| (id.as_str(), record))
+        .collect();
+    let feature_ids: HashSet<&str> = feature_rows.iter().map(|(id, _)| id.as_str()).collect();
+
+    let without_label: Vec<String> = feature_rows
+        .iter()
+        .filter(|(id, _)| !labels.contains_key(id.as_str()))
+        .map(|(id, _)| id.clone())
+        .collect();
+    let without_features: Vec<String> = label_rows
+        .iter()
+        .filter(|(id, _)| !feature_ids.contains(id.as_str()))
+        .map(|(id, _)| id.clone())
+        .collect();
+
+    if !without_label.is_empty() || !without_features.is_empty() {
+        return Err(JoinError::UnmatchedIds {
+            without_label,
+            without_features,
+        }
+        .into());
+    }
+
+    let mut entries = Vec::with_capacity(feature_rows.len());
+
+    for (id, record) in &feature_rows {
+        let label = labels[id.as_str()]
+            .get(spec.label_column)
+            .ok_or_else(|| format!("{labels_path}: no label column for id {id}"))?
+            .to_string();
+
+        entries.push((label, select_features(record, &spec.feature_columns)));
+    }
+
+    Ok(entries)
+}
+
+/// Label and numeric features of every row, cells that are not numbers are
+/// skipped
+pub fn parse_generic(
+    file_path: &str,
+    label_col: usize,
+    feature_cols: &[Range<usize>],
+) -> Result<Vec<(String, Vec<f64>)>, Box<dyn Error>> {
+    parse_generic_reader(File::open(file_path)?, label_col, feature_cols)
+}
+
+pub fn parse_generic_reader<R: Read>(
+    reader: R,
+    label_col: usize,
+    feature_cols: &[Range<usize>],
+) -> Result<Vec<(String, Vec<f64>)>, Box<dyn Error>> {
+    Ok(parse_generic_cells_reader(reader, label_col, feature_cols)?
+        .into_iter()
+        .map(|(label, cells)| (label, cells.into_iter().flatten().collect()))
+        .collect())
+}
+
+/// Like [`parse_generic_reader`], but keeps a `None` for every feature cell
+/// that is not a number, so all rows stay the same width
+pub fn parse_generic_cells_reader<R: Read>(
+    reader: R,
+    label_col: usize,
+    feature_cols: &[Range<usize>],
+) -> Result<Vec<(String, Vec<Option<f64>>)>, Box<dyn Error>> {
+    let mut reader = csv_reader(reader)?;
+
+    let mut entries = Vec::new();
+
+    for result in reader.records() {
+        let record = result?;
+        let label = field(&record, label_col)?.to_string();
+
+        entries.push((label, select_cells(&record, feature_cols)));
+    }
+
+    Ok(entries)
+}
+
+fn select_features(record: &StringRecord, feature_cols: &[Range<usize>]) -> Vec<f64> {
+    select_cells(record, feature_cols)
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+fn select_cells(record: &StringRecord, feature_cols: &[Range<usize>]) -> Vec<Option<f64>> {
+    record
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| feature_cols.iter().any(|columns| columns.contains(index)))
+        .map(|(_, value)| parse_cell(value))
+        .collect()
+}
+
+fn read_keyed(
+    file_path: &str,
+    join_column: &str,
+) -> Result<Vec<(String, StringRecord)>, Box<dyn Error>> {
+    let file = File::open(file_path)?;
+    // the join column is looked up by name, so a header is required
+    let mut reader = sniff_file(file_path)?
+        .reader_builder()
+        .has_headers(true)
+        .from_reader(BufReader::new(file));
+
+    let id_index = reader
+        .headers()?
+        .iter()
+        .position(|header| header == join_column)
+        .ok_or_else(|| JoinError::MissingColumn {
+            file: file_path.to_string(),
+            column: join_column.to_string(),
+        })?;
+
+    let mut rows = Vec::new();
+    let mut seen = HashSet::new();
+    let mut duplicates = Vec::new();
+
+    for result in reader.records() {
+        let record = result?;
+        let id = record.get(id_index).unwrap_or_default().to_string();
+
+        if !seen.insert(id.clone()) {
+            duplicates.push(id.clone());
+        }
+
+        rows.push((id, record));
+    }
+
+    if !duplicates.is_empty() {
+        return Err(JoinError::DuplicateIds {
+            file: file_path.to_string(),
+            ids: duplicates,
+        }
+        .into());
+    }
+
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+
+    const SAMPLE: &str = "\
+id,name,kind,width,height,depth
+1,first,a,1.5,2,x
+2,second,b,3,,4.25
+";
+
+    const ALL_FEATURES: Range<usize> = 3..6;
+
+    #[test]
+    fn in_memory_csvs_parse_by_column_index() {
+        let entries = parse_generic_reader(SAMPLE.as_bytes(), 2, &[3..4, 5..6]).unwrap();
+
+        assert_eq!(
+            entries,
+            [
+                ("a".to_string(), vec![1.5]),
+                ("b".to_string(), vec![3.0, 4.25]),
+            ]
+        );
+    }
+
+    #[test]
+    fn cells_keep_their_place_when_not_numeric() {
+        let entries = parse_generic_cells_reader(SAMPLE.as_bytes(), 2, &[ALL_FEATURES]).unwrap();
+
+        assert_eq!(
+            entries,
+            [
+                ("a".to_string(), vec![Some(1.5), Some(2.0), None]),
+                ("b".to_string(), vec![Some(3.0), None, Some(4.25)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_label_columns_are_an_error() {
+        assert!(parse_generic_reader(SAMPLE.as_bytes(), 9, &[ALL_FEATURES]).is_err());
+    }
+
+    const JOINED_FEATURES: Range<usize> = 1..3;
+
+    /// Joins the two CSVs on their `id` column through files in a scratch
+    /// directory named after `name`
+    fn join(
+        name: &str,
+        features: &str,
+        labels: &str,
+    ) -> Result<Vec<(String, Vec<f64>)>, JoinError> {
+        let dir = env::temp_dir().join(format!("knn-join-{name}-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let features_path = dir.join("features.csv");
+        let labels_path = dir.join("labels.csv");
+        fs::write(&features_path, features).unwrap();
+        fs::write(&labels_path, labels).unwrap();
+
+        let entries = parse_joined(
+            features_path.to_str().unwrap(),
+            labels_path.to_str().unwrap(),
+            "id",
+            &ColumnSpec {
+                label_column: 1,
+                feature_columns: vec![JOINED_FEATURES],
+            },
+        );
+        fs::remove_dir_all(&dir).unwrap();
+
+        entries.map_err(|error| *error.downcast::<JoinError>().unwrap())
+    }
+
+    #[test]
+    fn joined_files_take_labels_by_id_in_feature_order() {
+        let entries = join("clean", "id,x,y\n7,1.5,2\n3,4,5\n", "id,label\n3,b\n7,a\n").unwrap();
+
+        assert_eq!(
+            entries,
+            [
+                ("a".to_string(), vec![1.5, 2.0]),
+                ("b".to_string(), vec![4.0, 5.0]),
+            ]
+        );
+    }
+
+    #[test]
+    fn ids_missing_from_either_file_are_reported() {
+        assert_eq!(
+            join(
+                "unmatched",
+                "id,x,y\n1,0,0\n2,0,0\n",
+                "id,label\n2,a\n3,b\n",
+            ),
+            Err(JoinError::UnmatchedIds {
+                without_label: vec!["1".to_string()],
+                without_features: vec!["3".to_string()],
+            })
+        );
+    }
+
+    #[test]
+    fn duplicate_ids_in_either_file_are_reported() {
+        let features = join(
+            "duplicate-features",
+            "id,x,y\n1,0,0\n1,1,1\n",
+            "id,label\n1,a\n",
+        );
+        let labels = join(
+            "duplicate-labels",
+            "id,x,y\n1,0,0\n",
+            "id,label\n1,a\n1,b\n",
+        );
+
+        assert!(matches!(
+            features,
+            Err(JoinError::DuplicateIds { file, ids }) if file.ends_with("features.csv") && ids == ["1"]
+        ));
+        assert!(matches!(
+            labels,
+            Err(JoinError::DuplicateIds { file, ids }) if file.ends_with("labels.csv") && ids == ["1"]
+        ));
+    }
+}
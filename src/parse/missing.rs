@@ -0,0 +1,54 @@
+/// Empty and non-numeric cells count as missing
+pub fn parse_cell(value: &str) -> Option<f64> {
+    value.trim().parse::<f64>().ok()
+}
+
+/// Fills every missing cell with the mean of the present cells of its
+/// column, or with zero when the whole column is missing
+pub fn impute_mean(rows: &[Vec<Option<f64>>]) -> Vec<Vec<f64>> {
+    let width = rows.first().map_or(0, Vec::len);
+    let mut sums = vec![0.0; width];
+    let mut counts = vec![0_usize; width];
+
+    for row in rows {
+        for ((sum, count), value) in sums.iter_mut().zip(&mut counts).zip(row) {
+            if let Some(value) = value {
+                *sum += value;
+                *count += 1;
+            }
+        }
+    }
+
+    let means: Vec<f64> = sums
+        .iter()
+        .zip(&counts)
+        .map(|(&sum, &count)| if count > 0 { sum / count as f64 } else { 0.0 })
+        .collect();
+
+    rows.iter()
+        .map(|row| {
+            row.iter()
+                .zip(&means)
+                .map(|(value, &mean)| value.unwrap_or(mean))
+                .collect()
+        })
+        .collect()
+}
+
+/// A 0/1 indicator per row for every column that has any missing cell, in
+/// column order. Columns without missing cells get no indicator
+pub fn missing_indicators(rows: &[Vec<Option<f64>>]) -> Vec<Vec<f64>> {
+    let width = rows.first().map_or(0, Vec::len);
+    let incomplete_columns: Vec<usize> = (0..width)
+        .filter(|&column| rows.iter().any(|row| row[column].is_none()))
+        .collect();
+
+    rows.iter()
+        .map(|row| {
+            incomplete_columns
+                .iter()
+                .map(|&column| if row[column].is_none() { 1.0 } else { 0.0 })
+                .collect()
+        })
+        .collect()
+}
@@ -0,0 +1,92 @@
+/// Maps every value into `[0, 1]`, a constant input maps to all zeros
+pub fn min_max_normalize(data: &[f64]) -> Vec<f64> {
+    let min = data.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = data.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let range = max - min;
+
+    if range > 0.0 {
+        data.iter().map(|&x| (x - min) / range).collect()
+    } else {
+        vec![0.0; data.len()]
+    }
+}
+
+/// Standardizes every column independently to zero mean and unit variance,
+/// a constant column maps to all zeros
+pub fn z_score_columns(rows: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    let width = rows.first().map_or(0, Vec::len);
+    let count = rows.len() as f64;
+
+    let means: Vec<f64> = (0..width)
+        .map(|column| rows.iter().map(|row| row[column]).sum::<f64>() / count)
+        .collect();
+    let std_devs: Vec<f64> = (0..width)
+        .map(|column| {
+            let variance = rows
+                .iter()
+                .map(|row| (row[column] - means[column]).powi(2))
+                .sum::<f64>()
+                / count;
+
+            variance.sqrt()
+        })
+        .collect();
+
+    rows.iter()
+        .map(|row| {
+            row.iter()
+                .zip(means.iter().zip(&std_devs))
+                .map(|(&x, (&mean, &std_dev))| {
+                    if std_dev > 0.0 {
+                        (x - mean) / std_dev
+                    } else {
+                        0.0
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn z_score_columns_standardize_each_column_independently() {
+        let rows = vec![vec![1.0, 100.0], vec![2.0, 300.0], vec![3.0, 200.0]];
+
+        let normalized = z_score_columns(&rows);
+
+        for column in 0..2 {
+            let values: Vec<f64> = normalized.iter().map(|row| row[column]).collect();
+            let mean = values.iter().sum::<f64>() / values.len() as f64;
+            let variance =
+                values.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / values.len() as f64;
+
+            assert!(mean.abs() < 1e-9);
+            assert!((variance - 1.0).abs() < 1e-9);
+        }
+        // the second column's order is kept without leaking into the first
+        assert!(normalized[0][0] < normalized[1][0] && normalized[1][0] < normalized[2][0]);
+        assert!(normalized[0][1] < normalized[2][1] && normalized[2][1] < normalized[1][1]);
+    }
+
+    #[test]
+    fn min_max_maps_mixed_signs_into_the_unit_interval() {
+        let normalized = min_max_normalize(&[-2.0, 0.0, 6.0]);
+
+        let expected = [0.0, 0.25, 1.0];
+        for (value, expected) in normalized.iter().zip(expected) {
+            assert!((value - expected).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn min_max_maps_a_constant_input_to_zeros() {
+        let normalized = min_max_normalize(&[3.5, 3.5, 3.5]);
+
+        assert_eq!(normalized.len(), 3);
+        assert!(normalized.iter().all(|value| value.abs() < f64::EPSILON));
+    }
+}
@@ -1,45 +1,88 @@
-use csv::ReaderBuilder;
 use std::error::Error;
 use std::fs::File;
-use std::io::BufReader;
+use std::io::Read;
 
+use super::{
+    field,
+    missing::{impute_mean, missing_indicators, parse_cell},
+    normalize::z_score_columns,
+    sniff::csv_reader,
+    ParseError,
+};
+
+/// Six numeric features followed by gender
+pub const DIMENSIONS: usize = 7;
+
+/// Gender is the last feature and is kept as a raw 0.0/1.0 categorical
+/// value, meant to be compared with `Gower<1>`
 #[derive(Debug)]
 pub struct CsvEntry {
     pub os: PhoneOs,
     pub values: Vec<f64>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum PhoneOs {
     Android,
     IOs,
 }
 
-pub fn to_os(os: &str) -> PhoneOs {
+pub fn to_os(os: &str) -> Result<PhoneOs, ParseError> {
     match os {
-        "Android" => PhoneOs::Android,
-        "iOS" => PhoneOs::IOs,
+        "Android" => Ok(PhoneOs::Android),
+        "iOS" => Ok(PhoneOs::IOs),
         // dataset only contains android and iphone
-        val => panic!("unexpected os {val}"),
+        val => Err(ParseError::UnknownOs(val.to_string())),
     }
 }
 
-pub fn normalize(data: &[f64]) -> Vec<f64> {
-    let mean = data.iter().copied().sum::<f64>() / data.len() as f64;
-    let variance = data.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / data.len() as f64;
-    let std_dev = variance.sqrt();
-
-    data.iter().map(|&x| (x - mean) / std_dev).collect()
+pub fn to_gender(gender: &str) -> Result<f64, ParseError> {
+    match gender {
+        "Female" => Ok(0.0),
+        "Male" => Ok(1.0),
+        // dataset contains only male and female
+        val => Err(ParseError::UnknownGender(val.to_string())),
+    }
 }
 
 pub fn parse(file_path: &str) -> Result<Vec<CsvEntry>, Box<dyn Error>> {
-    let file = File::open(file_path)?;
-    let mut reader = ReaderBuilder::new()
-        .has_headers(true)
-        .from_reader(BufReader::new(file));
+    parse_reader(File::open(file_path)?)
+}
 
-    let mut entries = Vec::new();
-    let mut values_list = Vec::new();
+pub fn parse_reader<R: Read>(reader: R) -> Result<Vec<CsvEntry>, Box<dyn Error>> {
+    Ok(to_entries(parse_rows(reader)?))
+}
+
+/// Like [`parse`], but adds a 0/1 feature for every numeric column with
+/// missing cells, set where the original cell was missing. The extra features
+/// are not normalized and go before gender, so gender stays last
+pub fn parse_with_missing_indicators(file_path: &str) -> Result<Vec<CsvEntry>, Box<dyn Error>> {
+    Ok(with_missing_indicators(parse_rows(File::open(file_path)?)?))
+}
+
+fn with_missing_indicators(rows: Vec<Row>) -> Vec<CsvEntry> {
+    let cells: Vec<Vec<Option<f64>>> = rows.iter().map(|row| row.cells.clone()).collect();
+
+    let mut entries = to_entries(rows);
+    for (entry, indicators) in entries.iter_mut().zip(missing_indicators(&cells)) {
+        let gender_index = entry.values.len() - 1;
+        entry.values.splice(gender_index..gender_index, indicators);
+    }
+
+    entries
+}
+
+/// An os with its raw, possibly missing, numeric cells and its gender
+struct Row {
+    os: PhoneOs,
+    cells: Vec<Option<f64>>,
+    gender: f64,
+}
+
+fn parse_rows<R: Read>(reader: R) -> Result<Vec<Row>, Box<dyn Error>> {
+    let mut reader = csv_reader(reader)?;
+
+    let mut rows = Vec::new();
 
     for result in reader.records() {
         const OS_FIELD_INDEX: usize = 2;
@@ -49,49 +92,140 @@ pub fn parse(file_path: &str) -> Result<Vec<CsvEntry>, Box<dyn Error>> {
 
         let record = result?;
 
-        let os = record.get(OS_FIELD_INDEX).unwrap().to_string();
-        let gender = record.get(GENDER_FIELD_INDEX).unwrap().to_string();
+        let os = to_os(field(&record, OS_FIELD_INDEX)?)?;
+        let gender = to_gender(field(&record, GENDER_FIELD_INDEX)?)?;
 
-        let mut values: Vec<f64> = record
-            .iter()
-            .enumerate()
-            .filter_map(|(index, value)| {
-                if (NUMERIC_FIELD_START..=NUMERIC_FIELD_END).contains(&index) {
-                    value.parse::<f64>().ok()
-                } else {
-                    None
-                }
-            })
+        let cells = (NUMERIC_FIELD_START..=NUMERIC_FIELD_END)
+            .map(|index| record.get(index).and_then(parse_cell))
             .collect();
 
-        values_list.push(values.clone());
+        rows.push(Row { os, cells, gender });
+    }
+
+    Ok(rows)
+}
+
+fn to_entries(rows: Vec<Row>) -> Vec<CsvEntry> {
+    let cells: Vec<Vec<Option<f64>>> = rows.iter().map(|row| row.cells.clone()).collect();
+    let values_list = z_score_columns(&impute_mean(&cells));
+
+    rows.into_iter()
+        .zip(values_list)
+        .map(|(row, mut values)| {
+            values.push(row.gender);
+            CsvEntry { os: row.os, values }
+        })
+        .collect()
+}
 
-        let gender_value = match gender.as_str() {
-            "Female" => 0.0,
-            "Male" => 1.0,
-            // dataset contains only male and female
-            val => panic!("unexpected gender {val}"),
-        };
+/// Concatenates the raw rows of every file before imputing and normalizing,
+/// so all files share one set of column statistics
+pub fn parse_many(paths: &[&str]) -> Result<Vec<CsvEntry>, Box<dyn Error>> {
+    let rows = super::parse_many(
+        paths,
+        |path| parse_rows(File::open(path)?),
+        |row| row.cells.len(),
+    )?;
 
-        values.push(gender_value);
+    Ok(to_entries(rows))
+}
+
+#[cfg(test)]
+mod tests {
+    use kiddo::Manhattan;
+
+    use super::*;
+    use crate::{
+        kernel::Uniform,
+        knn::{Data, Knn, WindowType},
+    };
+
+    const SAMPLE: &str = "\
+User ID,Device Model,Operating System,App Usage Time (min/day),Screen On Time (hours/day),Battery Drain (mAh/day),Number of Apps Installed,Data Usage (MB/day),Age,Gender,User Behavior Class
+1,Google Pixel 5,Android,393,6.4,1872,67,1122,40,Male,4
+2,OnePlus 9,Android,410,6.9,1950,70,1180,38,Female,4
+3,Xiaomi Mi 11,Android,380,6.1,1800,64,1090,42,Male,4
+4,iPhone 12,iOS,95,1.9,600,15,300,25,Female,1
+5,iPhone 12,iOS,110,2.2,650,18,320,27,Male,1
+6,iPhone 12,iOS,100,2.0,620,16,310,24,Female,1
+";
+
+    #[test]
+    fn knn_predicts_phone_os_labels() {
+        let data: Vec<Data<PhoneOs, DIMENSIONS>> = parse_reader(SAMPLE.as_bytes())
+            .unwrap()
+            .into_iter()
+            .map(|entry| Data {
+                features: entry.values.try_into().unwrap(),
+                label: entry.os,
+            })
+            .collect();
 
-        let phone_os = to_os(&os);
-        entries.push(CsvEntry {
-            os: phone_os,
-            values,
-        });
+        let mut knn: Knn<Manhattan, PhoneOs, DIMENSIONS> =
+            Knn::with_params(1, 0.0, &WindowType::Unfixed, Uniform).unwrap();
+        knn.fit(data.clone(), None, false).unwrap();
+
+        assert_eq!(knn.classes(), [PhoneOs::Android, PhoneOs::IOs]);
+        for (index, data_point) in data.iter().enumerate() {
+            assert_eq!(
+                knn.predict_excluding(&data_point.features, index),
+                Ok(data_point.label)
+            );
+        }
     }
 
-    let normalized_values = normalize(&values_list.concat());
+    #[test]
+    fn gender_is_kept_raw_while_the_rest_is_z_scored() {
+        let entries = parse_reader(SAMPLE.as_bytes()).unwrap();
 
-    let value_length = entries.first().map_or(0, |entry| entry.values.len());
+        let genders: Vec<f64> = entries
+            .iter()
+            .map(|entry| entry.values[DIMENSIONS - 1])
+            .collect();
+        assert_eq!(genders, [1.0, 0.0, 1.0, 0.0, 1.0, 0.0]);
 
-    for (entry, new_values) in entries
-        .iter_mut()
-        .zip(normalized_values.chunks(value_length))
-    {
-        entry.values = new_values.to_vec();
+        let app_usage_mean =
+            entries.iter().map(|entry| entry.values[0]).sum::<f64>() / entries.len() as f64;
+        assert!(app_usage_mean.abs() < 1e-9);
     }
 
-    Ok(entries)
+    #[test]
+    fn missing_indicators_mark_exactly_the_missing_cells() {
+        let sample = SAMPLE
+            .replace("380,6.1,1800", "380,,1800")
+            .replace("95,1.9,600", "95,,600");
+
+        let entries = with_missing_indicators(parse_rows(sample.as_bytes()).unwrap());
+
+        // only the screen time column has gaps, so one indicator before gender
+        for (index, entry) in entries.iter().enumerate() {
+            assert_eq!(entry.values.len(), DIMENSIONS + 1);
+            let expected = if index == 2 || index == 3 { 1.0 } else { 0.0 };
+            assert!((entry.values[DIMENSIONS - 1] - expected).abs() < f64::EPSILON);
+        }
+        let genders: Vec<f64> = entries
+            .iter()
+            .map(|entry| entry.values[DIMENSIONS])
+            .collect();
+        assert_eq!(genders, [1.0, 0.0, 1.0, 0.0, 1.0, 0.0]);
+    }
+
+    fn parse_error(sample: &str) -> ParseError {
+        *parse_reader(sample.as_bytes())
+            .unwrap_err()
+            .downcast::<ParseError>()
+            .unwrap()
+    }
+
+    #[test]
+    fn unknown_os_and_gender_are_errors_instead_of_panics() {
+        assert_eq!(
+            parse_error(&SAMPLE.replace("iPhone 12,iOS", "Lumia 950,Windows")),
+            ParseError::UnknownOs("Windows".to_string())
+        );
+        assert_eq!(
+            parse_error(&SAMPLE.replace("40,Male", "40,Unknown")),
+            ParseError::UnknownGender("Unknown".to_string())
+        );
+    }
 }
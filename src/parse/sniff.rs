@@ -0,0 +1,146 @@
+use csv::{Reader, ReaderBuilder};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+
+const CANDIDATE_DELIMITERS: [u8; 4] = [b',', b';', b'\t', b'|'];
+const SAMPLE_LINES: usize = 5;
+/// Large enough to hold a few lines of the widest dataset
+const SAMPLE_BYTES: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dialect {
+    pub delimiter: u8,
+    pub has_headers: bool,
+}
+
+impl Default for Dialect {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            has_headers: true,
+        }
+    }
+}
+
+impl Dialect {
+    pub fn reader_builder(&self) -> ReaderBuilder {
+        let mut builder = ReaderBuilder::new();
+        builder
+            .delimiter(self.delimiter)
+            .has_headers(self.has_headers);
+
+        builder
+    }
+}
+
+/// Picks the candidate delimiter that splits every sample line into the same
+/// number of fields (the most fields wins), and treats the first line as a
+/// header when it has fewer numeric fields than the line after it
+pub fn sniff(sample: &str) -> Dialect {
+    let lines: Vec<&str> = sample
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .take(SAMPLE_LINES)
+        .collect();
+
+    if lines.is_empty() {
+        return Dialect::default();
+    }
+
+    let delimiter = CANDIDATE_DELIMITERS
+        .into_iter()
+        .filter_map(|delimiter| {
+            let counts: Vec<usize> = lines
+                .iter()
+                .map(|line| line.bytes().filter(|&byte| byte == delimiter).count())
+                .collect();
+
+            (counts[0] > 0 && counts.iter().all(|&count| count == counts[0]))
+                .then_some((delimiter, counts[0]))
+        })
+        .max_by_key(|&(_, count)| count)
+        .map_or(b',', |(delimiter, _)| delimiter);
+
+    let numeric_fields = |line: &str| {
+        line.split(char::from(delimiter))
+            .filter(|field| field.trim().trim_matches('"').parse::<f64>().is_ok())
+            .count()
+    };
+
+    let has_headers = match lines.get(1) {
+        Some(second) => numeric_fields(lines[0]) < numeric_fields(second),
+        None => numeric_fields(lines[0]) == 0,
+    };
+
+    Dialect {
+        delimiter,
+        has_headers,
+    }
+}
+
+/// Sniffs the buffered start of `reader` without consuming it
+pub fn sniff_reader<R: BufRead>(reader: &mut R) -> io::Result<Dialect> {
+    let sample = String::from_utf8_lossy(reader.fill_buf()?);
+
+    // the last buffered line may be cut off
+    let sample = match sample.trim_end().rfind('\n') {
+        Some(end) => &sample[..end],
+        None => &sample,
+    };
+
+    Ok(sniff(sample))
+}
+
+pub fn sniff_file(file_path: &str) -> io::Result<Dialect> {
+    sniff_reader(&mut BufReader::new(File::open(file_path)?))
+}
+
+/// CSV reader over `reader` configured with its sniffed dialect
+pub fn csv_reader<R: Read>(reader: R) -> io::Result<Reader<BufReader<R>>> {
+    let mut reader = BufReader::with_capacity(SAMPLE_BYTES, reader);
+    let dialect = sniff_reader(&mut reader)?;
+
+    Ok(dialect.reader_builder().from_reader(reader))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn semicolon_delimited_samples_are_detected() {
+        let dialect = sniff("id;diagnosis;radius\n1;M;17.99\n2;B;13.54\n");
+
+        assert_eq!(
+            dialect,
+            Dialect {
+                delimiter: b';',
+                has_headers: true,
+            }
+        );
+    }
+
+    #[test]
+    fn headerless_samples_are_detected() {
+        let dialect = sniff("1,M,17.99,10.38\n2,B,13.54,14.36\n");
+
+        assert_eq!(
+            dialect,
+            Dialect {
+                delimiter: b',',
+                has_headers: false,
+            }
+        );
+    }
+
+    #[test]
+    fn sniffed_readers_keep_the_first_row_of_headerless_files() {
+        let mut reader = csv_reader("1;M;17.99\n2;B;13.54\n3;B;12.1\n".as_bytes()).unwrap();
+
+        let records: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+
+        assert_eq!(records.len(), 3);
+        assert_eq!(&records[0][1], "M");
+        assert_eq!(&records[2][2], "12.1");
+    }
+}
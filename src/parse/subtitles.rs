@@ -1,7 +1,10 @@
-use csv::ReaderBuilder;
 use std::error::Error;
 use std::fs::File;
-use std::io::BufReader;
+use std::io::Read;
+
+use super::{generic::parse_generic_cells_reader, missing::impute_mean, ParseError};
+
+pub const DIMENSIONS: usize = 13;
 
 #[derive(Debug)]
 pub struct CsvEntry {
@@ -9,7 +12,7 @@ pub struct CsvEntry {
     pub values: Vec<f64>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Source {
     Original,
     Manga,
@@ -27,7 +30,7 @@ pub enum Source {
     Other,
 }
 
-pub fn to_source(source: &str) -> Result<Source, &str> {
+pub fn to_source(source: &str) -> Result<Source, ParseError> {
     match source {
         "Original" => Ok(Source::Original),
         "Manga" => Ok(Source::Manga),
@@ -43,45 +46,153 @@ pub fn to_source(source: &str) -> Result<Source, &str> {
         "Game" => Ok(Source::Game),
         "Multimedia Project" => Ok(Source::MultimediaProject),
         "Other" => Ok(Source::Other),
-        "?" => Err("no source"),
-        _ => panic!("unknown source: {source}"),
+        "?" => Err(ParseError::NoSource),
+        _ => Err(ParseError::UnknownSource(source.to_string())),
     }
 }
 
 pub fn parse(file_path: &str) -> Result<Vec<CsvEntry>, Box<dyn Error>> {
-    let file = File::open(file_path)?;
-    let mut reader = ReaderBuilder::new()
-        .has_headers(true)
-        .from_reader(BufReader::new(file));
-
-    let mut entries = Vec::new();
-
-    for result in reader.records() {
-        const SOURCE_FIELD_INDEX: usize = 30;
-        const FIRST_COMPANY_INDEX: usize = 37;
-        const LAST_COMPANY_INDEX: usize = 970;
-
-        let record = result?;
-        let source = record.get(SOURCE_FIELD_INDEX).unwrap().to_string();
-
-        let values: Vec<f64> = record
-            .iter()
-            .enumerate()
-            .filter_map(|(index, value)| {
-                if index <= SOURCE_FIELD_INDEX
-                    || (FIRST_COMPANY_INDEX..=LAST_COMPANY_INDEX).contains(&index)
-                {
-                    None
-                } else {
-                    value.parse::<f64>().ok()
-                }
-            })
-            .collect();
-
-        if let Ok(source) = to_source(&source) {
-            entries.push(CsvEntry { source, values });
+    parse_reader(File::open(file_path)?)
+}
+
+pub fn parse_reader<R: Read>(reader: R) -> Result<Vec<CsvEntry>, Box<dyn Error>> {
+    Ok(to_entries(parse_rows(reader)?))
+}
+
+/// A source with its raw, possibly missing, feature cells
+type Row = (Source, Vec<Option<f64>>);
+
+fn parse_rows<R: Read>(reader: R) -> Result<Vec<Row>, Box<dyn Error>> {
+    const SOURCE_FIELD_INDEX: usize = 30;
+    const FIRST_COMPANY_INDEX: usize = 37;
+    const LAST_COMPANY_INDEX: usize = 970;
+
+    let rows = parse_generic_cells_reader(
+        reader,
+        SOURCE_FIELD_INDEX,
+        &[
+            SOURCE_FIELD_INDEX + 1..FIRST_COMPANY_INDEX,
+            LAST_COMPANY_INDEX + 1..usize::MAX,
+        ],
+    )?;
+
+    let mut known = Vec::new();
+
+    for (source, row) in rows {
+        // rows without a known source can't be used for training
+        match to_source(&source) {
+            Ok(source) => known.push((source, row)),
+            Err(ParseError::NoSource) => {}
+            Err(error) => return Err(error.into()),
         }
     }
 
-    Ok(entries)
+    Ok(known)
+}
+
+fn to_entries(rows: Vec<Row>) -> Vec<CsvEntry> {
+    let (sources, cells): (Vec<Source>, Vec<Vec<Option<f64>>>) = rows.into_iter().unzip();
+
+    // empty cells are filled in rather than dropped, which would shift the
+    // remaining features
+    sources
+        .into_iter()
+        .zip(impute_mean(&cells))
+        .map(|(source, values)| CsvEntry { source, values })
+        .collect()
+}
+
+/// Concatenates the raw rows of every file before imputing, so all files
+/// share one set of column means
+pub fn parse_many(paths: &[&str]) -> Result<Vec<CsvEntry>, Box<dyn Error>> {
+    let rows = super::parse_many(
+        paths,
+        |path| parse_rows(File::open(path)?),
+        |(_, cells)| cells.len(),
+    )?;
+
+    Ok(to_entries(rows))
+}
+
+#[cfg(test)]
+mod tests {
+    use kiddo::Manhattan;
+
+    use super::*;
+    use crate::{
+        kernel::Uniform,
+        knn::{Data, Knn, WindowType},
+    };
+
+    /// Two clusters of `Source` labels at any width, apart on every axis
+    fn clusters<const D: usize>() -> Vec<Data<Source, D>> {
+        [
+            (0.0, Source::Manga),
+            (0.2, Source::Manga),
+            (5.0, Source::Anime),
+            (5.3, Source::Anime),
+        ]
+        .into_iter()
+        .map(|(position, label)| Data {
+            features: [position; D],
+            label,
+        })
+        .collect()
+    }
+
+    fn predict_at<const D: usize>(position: f64) -> Source {
+        let mut knn: Knn<Manhattan, Source, D> =
+            Knn::with_params(1, 0.0, &WindowType::Unfixed, Uniform).unwrap();
+        knn.fit(clusters(), None, false).unwrap();
+
+        knn.predict(&[position; D]).unwrap()
+    }
+
+    #[test]
+    fn knn_predicts_at_two_widths() {
+        assert_eq!(predict_at::<DIMENSIONS>(0.1), Source::Manga);
+        assert_eq!(predict_at::<DIMENSIONS>(4.8), Source::Anime);
+        assert_eq!(predict_at::<2>(0.1), Source::Manga);
+        assert_eq!(predict_at::<2>(4.8), Source::Anime);
+    }
+
+    /// A header and one row per source, with the source in column 30 and a
+    /// number in each of the 36 other columns up to the companies
+    fn with_sources(sources: &[&str]) -> String {
+        let header: Vec<String> = (0..37).map(|index| format!("c{index}")).collect();
+        let mut csv = header.join(",") + "\n";
+
+        for source in sources {
+            let row: Vec<String> = (0..37)
+                .map(|index| match index {
+                    30 => (*source).to_string(),
+                    _ => "1".to_string(),
+                })
+                .collect();
+            csv += &(row.join(",") + "\n");
+        }
+
+        csv
+    }
+
+    #[test]
+    fn unknown_sources_are_errors_and_missing_ones_are_skipped() {
+        assert_eq!(
+            to_source("Radio"),
+            Err(ParseError::UnknownSource("Radio".to_string()))
+        );
+        assert_eq!(to_source("?"), Err(ParseError::NoSource));
+
+        let rows = parse_rows(with_sources(&["Manga", "?", "Anime"]).as_bytes()).unwrap();
+        assert_eq!(
+            rows.iter().map(|(source, _)| *source).collect::<Vec<_>>(),
+            [Source::Manga, Source::Anime]
+        );
+
+        let error = parse_rows(with_sources(&["Manga", "Radio"]).as_bytes()).unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<ParseError>(),
+            Some(&ParseError::UnknownSource("Radio".to_string()))
+        );
+    }
 }
@@ -0,0 +1,210 @@
+use plotters::{
+    chart::ChartBuilder,
+    prelude::{BitMapBackend, Circle, IntoDrawingArea, PathElement, Rectangle},
+    series::LineSeries,
+    style::{Color, IntoFont, BLACK, BLUE, RED, WHITE},
+};
+use std::error::Error;
+
+use crate::search::ParamImportance;
+
+pub fn plot_param_importance(
+    importances: &[ParamImportance],
+    filename: &str,
+) -> Result<(), Box<dyn Error>> {
+    let root = BitMapBackend::new(filename, (800, 600)).into_drawing_area();
+    root.fill(&WHITE)?;
+
+    let axis_count = i32::try_from(importances.len())?;
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption("Hyperparameter importance", ("sans-serif", 40).into_font())
+        .margin(5)
+        .x_label_area_size(40)
+        .y_label_area_size(40)
+        .build_cartesian_2d(0..axis_count, 0.0..1.0)?;
+
+    chart
+        .configure_mesh()
+        .disable_x_mesh()
+        .x_labels(importances.len())
+        .x_label_formatter(&|index| {
+            usize::try_from(*index)
+                .ok()
+                .and_then(|index| importances.get(index))
+                .map_or_else(String::new, |importance| importance.axis.to_string())
+        })
+        .draw()?;
+
+    chart.draw_series(importances.iter().enumerate().map(|(index, importance)| {
+        let index = i32::try_from(index).unwrap();
+        Rectangle::new(
+            [(index, 0.0), (index + 1, importance.importance)],
+            BLUE.filled(),
+        )
+    }))?;
+
+    root.present()?;
+
+    Ok(())
+}
+
+pub fn plot_residuals(
+    actual: &[f64],
+    predicted: &[f64],
+    filename: &str,
+) -> Result<(), Box<dyn Error>> {
+    let points: Vec<(f64, f64)> = predicted
+        .iter()
+        .zip(actual)
+        .map(|(&predicted, &actual)| (predicted, actual - predicted))
+        .collect();
+
+    let (min_x, max_x) = bounds(points.iter().map(|point| point.0));
+    let (min_y, max_y) = bounds(points.iter().map(|point| point.1));
+
+    let root = BitMapBackend::new(filename, (1024, 768)).into_drawing_area();
+    root.fill(&WHITE)?;
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption("Residuals vs predicted", ("sans-serif", 50).into_font())
+        .margin(5)
+        .x_label_area_size(40)
+        .y_label_area_size(40)
+        .build_cartesian_2d(min_x..max_x, min_y..max_y)?;
+
+    chart.configure_mesh().draw()?;
+
+    chart.draw_series(
+        points
+            .iter()
+            .map(|&point| Circle::new(point, 3, BLUE.filled())),
+    )?;
+
+    chart
+        .draw_series(LineSeries::new(vec![(min_x, 0.0), (max_x, 0.0)], RED))?
+        .label("zero residual")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 10, y)], RED));
+
+    chart.configure_series_labels().border_style(BLACK).draw()?;
+    root.present()?;
+
+    Ok(())
+}
+
+/// Reliability diagram as returned by
+/// [`crate::metrics::reliability_diagram`], against the diagonal of a
+/// perfectly calibrated model
+pub fn plot_reliability_diagram(
+    points: &[(f64, f64)],
+    filename: &str,
+) -> Result<(), Box<dyn Error>> {
+    let root = BitMapBackend::new(filename, (800, 800)).into_drawing_area();
+    root.fill(&WHITE)?;
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption("Reliability diagram", ("sans-serif", 40).into_font())
+        .margin(5)
+        .x_label_area_size(40)
+        .y_label_area_size(40)
+        .build_cartesian_2d(0.0..1.0, 0.0..1.0)?;
+
+    chart
+        .configure_mesh()
+        .x_desc("mean predicted probability")
+        .y_desc("observed frequency")
+        .draw()?;
+
+    chart
+        .draw_series(LineSeries::new(vec![(0.0, 0.0), (1.0, 1.0)], RED))?
+        .label("perfectly calibrated")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 10, y)], RED));
+
+    chart
+        .draw_series(LineSeries::new(points.iter().copied(), BLUE))?
+        .label("model")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 10, y)], BLUE));
+    chart.draw_series(
+        points
+            .iter()
+            .map(|&point| Circle::new(point, 3, BLUE.filled())),
+    )?;
+
+    chart.configure_series_labels().border_style(BLACK).draw()?;
+    root.present()?;
+
+    Ok(())
+}
+
+pub fn plot_roc_curve(
+    points: &[(f64, f64)],
+    auc: f64,
+    filename: &str,
+) -> Result<(), Box<dyn Error>> {
+    let root = BitMapBackend::new(filename, (800, 800)).into_drawing_area();
+    root.fill(&WHITE)?;
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(
+            format!("ROC curve, AUC {auc:.3}"),
+            ("sans-serif", 40).into_font(),
+        )
+        .margin(5)
+        .x_label_area_size(40)
+        .y_label_area_size(40)
+        .build_cartesian_2d(0.0..1.0, 0.0..1.0)?;
+
+    chart
+        .configure_mesh()
+        .x_desc("false positive rate")
+        .y_desc("true positive rate")
+        .draw()?;
+
+    chart
+        .draw_series(LineSeries::new(vec![(0.0, 0.0), (1.0, 1.0)], RED))?
+        .label("chance")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 10, y)], RED));
+
+    chart
+        .draw_series(LineSeries::new(points.iter().copied(), BLUE))?
+        .label("model")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 10, y)], BLUE));
+
+    chart.configure_series_labels().border_style(BLACK).draw()?;
+    root.present()?;
+
+    Ok(())
+}
+
+/// Range of the values padded so that a single value still gets a non-empty axis
+fn bounds(values: impl Iterator<Item = f64>) -> (f64, f64) {
+    let (min, max) = values.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), value| {
+        (min.min(value), max.max(value))
+    });
+
+    if min > max {
+        return (-1.0, 1.0);
+    }
+
+    let padding = if max > min { (max - min) * 0.05 } else { 1.0 };
+    (min - padding, max + padding)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+
+    #[test]
+    fn residuals_are_written_as_a_png() {
+        let path = env::temp_dir().join(format!("knn-residuals-{}.png", std::process::id()));
+        let filename = path.to_str().unwrap();
+
+        plot_residuals(&[1.0, 2.0, 3.5, 4.0], &[1.2, 1.8, 3.0, 4.4], filename).unwrap();
+        let bytes = fs::read(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert!(bytes.unwrap().starts_with(b"\x89PNG"));
+    }
+}
@@ -0,0 +1,166 @@
+use kiddo::distance_metric::DistanceMetric;
+
+use crate::{
+    distance_metric::MetricKind,
+    kernel::Kernel,
+    knn::{Data, Knn, KnnError, WindowType},
+};
+
+/// Nadaraya-Watson regression on top of [`Knn`]: every training point is
+/// labelled with its own index, which maps back to its target value
+#[derive(Clone)]
+pub struct KnnRegressor<M: DistanceMetric<f64, D> + MetricKind, const D: usize> {
+    knn: Knn<M, usize, D>,
+    targets: Vec<f64>,
+}
+
+impl<M: DistanceMetric<f64, D> + MetricKind, const D: usize> KnnRegressor<M, D> {
+    pub fn with_params(
+        k: usize,
+        radius: f64,
+        window: &WindowType,
+        kernel: impl Kernel + 'static,
+    ) -> Result<Self, KnnError> {
+        Ok(Self {
+            knn: Knn::with_params(k, radius, window, kernel)?,
+            targets: Vec::new(),
+        })
+    }
+
+    #[deprecated(
+        note = "the tree is sized from the data passed to `fit`, use `KnnRegressor::with_params`"
+    )]
+    pub fn new(
+        k: usize,
+        radius: f64,
+        window: &WindowType,
+        kernel: impl Kernel + 'static,
+        _capacity: usize,
+    ) -> Result<Self, KnnError> {
+        Self::with_params(k, radius, window, kernel)
+    }
+
+    pub fn fit(
+        &mut self,
+        features: Vec<[f64; D]>,
+        targets: Vec<f64>,
+        weights: Option<Vec<f64>>,
+    ) -> Result<(), KnnError> {
+        if features.len() != targets.len() {
+            return Err(KnnError::InvalidInput(format!(
+                "expected {} targets, got {}",
+                features.len(),
+                targets.len()
+            )));
+        }
+
+        let data = features
+            .into_iter()
+            .enumerate()
+            .map(|(index, features)| Data {
+                features,
+                label: index,
+            })
+            .collect();
+
+        self.knn.fit(data, weights, false)?;
+        self.targets = targets;
+
+        Ok(())
+    }
+
+    /// Kernel-weighted mean of the neighbour targets, the plain mean when
+    /// every kernel weight is zero
+    pub fn predict_value(&self, x: &[f64; D]) -> Result<f64, KnnError> {
+        let neighbours = self.knn.predict_with_neighbors(x)?;
+
+        if neighbours.is_empty() {
+            return Err(self.knn.no_neighbors());
+        }
+
+        let mut weighted_sum = 0.0;
+        let mut total_weight = 0.0;
+        for neighbour in &neighbours {
+            let weight = neighbour.kernel_weight * neighbour.sample_weight;
+            weighted_sum += weight * self.targets[neighbour.label];
+            total_weight += weight;
+        }
+
+        if total_weight > 0.0 {
+            Ok(weighted_sum / total_weight)
+        } else {
+            let sum: f64 = neighbours
+                .iter()
+                .map(|neighbour| self.targets[neighbour.label])
+                .sum();
+            Ok(sum / neighbours.len() as f64)
+        }
+    }
+
+    /// The underlying model, e.g. to change its bandwidth or backend
+    pub fn knn_mut(&mut self) -> &mut Knn<M, usize, D> {
+        &mut self.knn
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kiddo::Manhattan;
+
+    use super::*;
+    use crate::kernel::uniform;
+
+    /// `function` sampled at the integers `0..=20`, embedded on the x axis
+    fn fitted(
+        window: WindowType,
+        kernel: fn(f64) -> f64,
+        function: fn(f64) -> f64,
+    ) -> KnnRegressor<Manhattan, 2> {
+        let xs: Vec<f64> = (0..=20).map(f64::from).collect();
+        let mut regressor = KnnRegressor::with_params(3, 1.5, &window, kernel).unwrap();
+        regressor
+            .fit(
+                xs.iter().map(|&x| [x, 0.0]).collect(),
+                xs.iter().map(|&x| function(x)).collect(),
+                None,
+            )
+            .unwrap();
+
+        regressor
+    }
+
+    #[test]
+    fn uniform_windows_average_the_neighbouring_samples() {
+        let linear = fitted(WindowType::Fixed, uniform, |x| 2.0 * x + 1.0);
+        let quadratic = fitted(WindowType::Fixed, uniform, |x| x * x);
+
+        for x in [3.0, 10.0, 17.0] {
+            // the window holds x - 1, x and x + 1
+            let linear_value = linear.predict_value(&[x, 0.0]).unwrap();
+            let quadratic_value = quadratic.predict_value(&[x, 0.0]).unwrap();
+
+            assert!((linear_value - (2.0 * x + 1.0)).abs() < 1e-9);
+            assert!((quadratic_value - (x * x + 2.0 / 3.0)).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn zero_kernel_weights_fall_back_to_the_plain_mean() {
+        let regressor = fitted(WindowType::Unfixed, |_| 0.0, |x| x * x);
+
+        // the three nearest samples are 4, 5 and 6
+        let value = regressor.predict_value(&[5.1, 0.0]).unwrap();
+
+        assert!((value - (16.0 + 25.0 + 36.0) / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn empty_windows_are_an_error() {
+        let regressor = fitted(WindowType::Fixed, uniform, |x| x);
+
+        assert_eq!(
+            regressor.predict_value(&[50.0, 0.0]),
+            Err(KnnError::NoNeighborsInRadius { radius: 1.5 })
+        );
+    }
+}
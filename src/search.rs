@@ -0,0 +1,466 @@
+use std::{error::Error, fmt, sync::Arc};
+
+use crate::{
+    kernel::{Kernel, Uniform},
+    knn::{VoteWeighting, WindowType},
+};
+
+#[derive(Clone)]
+pub struct Hyperparameters {
+    pub k: usize,
+    pub radius: f64,
+    pub window: WindowType,
+    pub kernel: Arc<dyn Kernel>,
+    pub voting: VoteWeighting,
+    pub metric: String,
+}
+
+impl Hyperparameters {
+    pub fn new() -> Self {
+        Self {
+            k: 0,
+            radius: 0.0,
+            window: WindowType::Fixed,
+            kernel: Arc::new(Uniform),
+            voting: VoteWeighting::Kernel,
+            metric: String::new(),
+        }
+    }
+}
+
+impl fmt::Debug for Hyperparameters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hyperparameters")
+            .field("k", &self.k)
+            .field("radius", &self.radius)
+            .field("window", &self.window)
+            .field("kernel", &self.kernel.name())
+            .field("voting", &self.voting)
+            .field("metric", &self.metric)
+            .finish()
+    }
+}
+
+impl Default for Hyperparameters {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GridError {
+    pub invalid_ks: Vec<usize>,
+    pub invalid_radii: Vec<f64>,
+}
+
+impl fmt::Display for GridError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid search grid:")?;
+
+        if !self.invalid_ks.is_empty() {
+            write!(f, " k must be positive, got {:?};", self.invalid_ks)?;
+        }
+        if !self.invalid_radii.is_empty() {
+            write!(f, " radius must be positive, got {:?};", self.invalid_radii)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Error for GridError {}
+
+#[derive(Debug, Clone)]
+pub struct GridSearch {
+    ks: Vec<usize>,
+    radii: Vec<f64>,
+}
+
+impl GridSearch {
+    /// Reports every invalid value of every axis at once
+    pub fn new(ks: Vec<usize>, radii: Vec<f64>) -> Result<Self, GridError> {
+        let invalid_ks: Vec<usize> = ks.iter().copied().filter(|&k| k == 0).collect();
+        let invalid_radii: Vec<f64> = radii
+            .iter()
+            .copied()
+            .filter(|radius| !(radius.is_finite() && *radius > 0.0))
+            .collect();
+
+        if !invalid_ks.is_empty() || !invalid_radii.is_empty() {
+            return Err(GridError {
+                invalid_ks,
+                invalid_radii,
+            });
+        }
+
+        Ok(Self { ks, radii })
+    }
+
+    #[must_use]
+    pub fn ks(&self) -> &[usize] {
+        &self.ks
+    }
+
+    #[must_use]
+    pub fn radii(&self) -> &[f64] {
+        &self.radii
+    }
+}
+
+/// Index of the highest score, the earliest one on ties so the choice does
+/// not depend on the order candidates were evaluated in
+#[must_use]
+pub fn best_candidate(scores: &[f64]) -> Option<usize> {
+    let mut best: Option<usize> = None;
+
+    for (index, score) in scores.iter().enumerate() {
+        if best.is_none_or(|best| *score > scores[best]) {
+            best = Some(index);
+        }
+    }
+
+    best
+}
+
+/// Like [`best_candidate`], but of the candidates scoring within `epsilon`
+/// of the best picks the one with the largest k, the smoothest model, as
+/// near-ties on a validation split say little about which generalizes.
+/// Still the earliest one among equal k
+#[must_use]
+pub fn best_candidate_preferring_larger_k(
+    scores: &[f64],
+    ks: &[usize],
+    epsilon: f64,
+) -> Option<usize> {
+    let best_score = scores[best_candidate(scores)?];
+    let mut best: Option<usize> = None;
+
+    for (index, (score, k)) in scores.iter().zip(ks).enumerate() {
+        if best_score - score <= epsilon && best.is_none_or(|best| *k > ks[best]) {
+            best = Some(index);
+        }
+    }
+
+    best
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchRecord {
+    pub k: usize,
+    pub radius: f64,
+    pub kernel: String,
+    pub window: WindowType,
+    pub metric: String,
+    pub score: f64,
+    pub mean_points_examined: Option<f64>,
+}
+
+impl fmt::Display for SearchRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "kernel: {}, window: {}, neighbours: {}, radius: {}, metric: {}\taccuracy: {:.3}%",
+            self.kernel,
+            format!("{:?}", self.window).to_lowercase(),
+            self.k,
+            self.radius,
+            self.metric,
+            self.score
+        )?;
+
+        if let Some(mean_points_examined) = self.mean_points_examined {
+            write!(f, ", points examined: {mean_points_examined:.1}")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ParamImportance {
+    pub axis: &'static str,
+    pub importance: f64,
+    pub level_means: Vec<(String, f64)>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SearchResult {
+    pub records: Vec<SearchRecord>,
+}
+
+impl SearchResult {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, record: SearchRecord) {
+        self.records.push(record);
+    }
+
+    /// Records from the best to the worst score, equal scores ordered by
+    /// metric, kernel, window, k and radius, so the order never depends on
+    /// the order the records were pushed in
+    #[must_use]
+    pub fn sorted(&self) -> Vec<&SearchRecord> {
+        let mut records: Vec<&SearchRecord> = self.records.iter().collect();
+
+        records.sort_by(|first, second| {
+            second
+                .score
+                .total_cmp(&first.score)
+                .then_with(|| first.metric.cmp(&second.metric))
+                .then_with(|| first.kernel.cmp(&second.kernel))
+                .then_with(|| format!("{:?}", first.window).cmp(&format!("{:?}", second.window)))
+                .then_with(|| first.k.cmp(&second.k))
+                .then_with(|| first.radius.total_cmp(&second.radius))
+        });
+
+        records
+    }
+
+    /// Ratio of between-group to total score variance for every
+    /// hyperparameter axis, sorted from the most to the least important
+    #[must_use]
+    pub fn param_importance(&self) -> Vec<ParamImportance> {
+        let axes: [(&'static str, fn(&SearchRecord) -> String); 5] = [
+            ("k", |record| record.k.to_string()),
+            ("radius", |record| record.radius.to_string()),
+            ("kernel", |record| record.kernel.clone()),
+            ("window", |record| {
+                format!("{:?}", record.window).to_lowercase()
+            }),
+            ("metric", |record| record.metric.clone()),
+        ];
+
+        let mut importances: Vec<ParamImportance> = axes
+            .into_iter()
+            .map(|(axis, level_of)| self.axis_importance(axis, level_of))
+            .collect();
+
+        importances.sort_by(|first, second| second.importance.total_cmp(&first.importance));
+        importances
+    }
+
+    fn axis_importance(
+        &self,
+        axis: &'static str,
+        level_of: fn(&SearchRecord) -> String,
+    ) -> ParamImportance {
+        if self.records.is_empty() {
+            return ParamImportance {
+                axis,
+                importance: 0.0,
+                level_means: Vec::new(),
+            };
+        }
+
+        let mean =
+            self.records.iter().map(|record| record.score).sum::<f64>() / self.records.len() as f64;
+        let total_sum_of_squares: f64 = self
+            .records
+            .iter()
+            .map(|record| (record.score - mean).powi(2))
+            .sum();
+
+        // (level, score sum, count) in order of first appearance
+        let mut levels: Vec<(String, f64, usize)> = Vec::new();
+        for record in &self.records {
+            let level = level_of(record);
+            match levels.iter_mut().find(|(name, _, _)| *name == level) {
+                Some((_, sum, count)) => {
+                    *sum += record.score;
+                    *count += 1;
+                }
+                None => levels.push((level, record.score, 1)),
+            }
+        }
+
+        let between_sum_of_squares: f64 = levels
+            .iter()
+            .map(|&(_, sum, count)| count as f64 * (sum / count as f64 - mean).powi(2))
+            .sum();
+
+        let importance = if total_sum_of_squares > 0.0 {
+            between_sum_of_squares / total_sum_of_squares
+        } else {
+            0.0
+        };
+
+        ParamImportance {
+            axis,
+            importance,
+            level_means: levels
+                .into_iter()
+                .map(|(level, sum, count)| (level, sum / count as f64))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+    use super::*;
+    use crate::kernel::Epanechnikov;
+
+    #[test]
+    fn hyperparameters_show_their_kernel_by_name() {
+        let hyperparameters = Hyperparameters {
+            kernel: Arc::new(Epanechnikov),
+            ..Hyperparameters::new()
+        };
+
+        assert_eq!(Hyperparameters::new().kernel.name(), "uniform");
+        assert!(format!("{hyperparameters:?}").contains("kernel: \"epanechnikov\""));
+    }
+
+    #[test]
+    fn grid_errors_list_every_invalid_value() {
+        let error = GridSearch::new(
+            vec![0, 3, 0, 5],
+            vec![1.0, 0.0, -2.0, f64::NAN, 4.0, f64::INFINITY],
+        )
+        .unwrap_err();
+
+        assert_eq!(error.invalid_ks, [0, 0]);
+        // NaN never equals itself, so the radii are compared bit for bit
+        assert_eq!(
+            error
+                .invalid_radii
+                .iter()
+                .map(|radius| radius.to_bits())
+                .collect::<Vec<_>>(),
+            [0.0, -2.0, f64::NAN, f64::INFINITY].map(f64::to_bits)
+        );
+        assert_eq!(
+            error.to_string(),
+            "invalid search grid: k must be positive, got [0, 0]; \
+             radius must be positive, got [0.0, -2.0, NaN, inf];"
+        );
+
+        let grid = GridSearch::new(vec![1, 2], vec![0.5]).unwrap();
+        assert_eq!(grid.ks(), [1, 2]);
+        assert_eq!(grid.radii().len(), 1);
+    }
+
+    #[test]
+    fn param_importance_ranks_the_only_axis_that_matters_first() {
+        let mut search_result = SearchResult::new();
+        for k in 1..=3 {
+            for radius in [0.5, 1.0] {
+                for kernel in ["uniform", "gaussian"] {
+                    for window in [WindowType::Fixed, WindowType::Unfixed] {
+                        for metric in ["manhattan", "chebyshev"] {
+                            search_result.push(SearchRecord {
+                                k,
+                                radius,
+                                kernel: kernel.to_string(),
+                                window,
+                                metric: metric.to_string(),
+                                score: if matches!(window, WindowType::Fixed) {
+                                    60.0
+                                } else {
+                                    90.0
+                                },
+                                mean_points_examined: None,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        let importances = search_result.param_importance();
+
+        assert_eq!(importances.len(), 5);
+        assert_eq!(importances[0].axis, "window");
+        assert!((importances[0].importance - 1.0).abs() < 1e-12);
+        assert_eq!(
+            importances[0].level_means,
+            [("fixed".to_string(), 60.0), ("unfixed".to_string(), 90.0)]
+        );
+        for importance in &importances[1..] {
+            assert!(importance.importance.abs() < 1e-12, "{importance:?}");
+        }
+    }
+
+    #[test]
+    fn param_importance_of_no_records_is_zero() {
+        let importances = SearchResult::new().param_importance();
+
+        assert_eq!(importances.len(), 5);
+        for importance in importances {
+            assert!(importance.importance.abs() < f64::EPSILON);
+            assert!(importance.level_means.is_empty());
+        }
+    }
+
+    fn records() -> Vec<SearchRecord> {
+        let mut records = Vec::new();
+
+        for (metric, kernel) in [("manhattan", "uniform"), ("chebyshev", "gaussian")] {
+            for k in 1..=4 {
+                for window in [WindowType::Fixed, WindowType::Unfixed] {
+                    records.push(SearchRecord {
+                        k,
+                        radius: 0.5 * k as f64,
+                        kernel: kernel.to_string(),
+                        window,
+                        metric: metric.to_string(),
+                        // plenty of equal scores, so the tie order is tested
+                        score: 90.0 + (k % 2) as f64,
+                        mean_points_examined: None,
+                    });
+                }
+            }
+        }
+
+        records
+    }
+
+    /// The log of one run that evaluated the candidates in a seeded order
+    fn sorted_log(seed: u64) -> String {
+        let mut records = records();
+        records.shuffle(&mut StdRng::seed_from_u64(seed));
+
+        let mut result = SearchResult::new();
+        for record in records {
+            result.push(record);
+        }
+
+        result
+            .sorted()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn sorted_logs_do_not_depend_on_evaluation_order() {
+        let first = sorted_log(1);
+
+        assert_eq!(first.as_bytes(), sorted_log(2).as_bytes());
+        assert_eq!(first.as_bytes(), sorted_log(3).as_bytes());
+        assert_eq!(first.lines().count(), records().len());
+    }
+
+    #[test]
+    fn near_ties_select_the_larger_k() {
+        let scores = [95.0, 94.9, 90.0];
+        let ks = [3, 15, 40];
+
+        assert_eq!(best_candidate(&scores), Some(0));
+        assert_eq!(
+            best_candidate_preferring_larger_k(&scores, &ks, 0.5),
+            Some(1)
+        );
+        // outside epsilon the more accurate config still wins
+        assert_eq!(
+            best_candidate_preferring_larger_k(&scores, &ks, 0.05),
+            Some(0)
+        );
+        assert_eq!(best_candidate_preferring_larger_k(&[], &[], 0.5), None);
+    }
+}
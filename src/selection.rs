@@ -0,0 +1,87 @@
+use kiddo::distance_metric::DistanceMetric;
+
+use crate::{
+    distance_metric::MetricKind,
+    kernel,
+    knn::{Data, Knn, KnnBuilder, KnnError, Label, VoteWeighting, WindowType},
+};
+
+/// Edited Nearest Neighbour noise filtering: drops every point whose label
+/// loses the majority vote of its `k` nearest other points. Returns the kept
+/// points in their original order and the indices of the dropped ones
+pub fn edit_enn<M, L, const D: usize>(
+    data: &[Data<L, D>],
+    k: usize,
+) -> Result<(Vec<Data<L, D>>, Vec<usize>), KnnError>
+where
+    M: DistanceMetric<f64, D> + MetricKind,
+    L: Label,
+{
+    let mut knn: Knn<M, L, D> = KnnBuilder::new()
+        .k(k)
+        .window(WindowType::Unfixed)
+        .kernel(kernel::Uniform)
+        .voting(VoteWeighting::Uniform)
+        .build()?;
+    knn.fit(data.to_vec(), None, false)?;
+
+    let mut kept = Vec::with_capacity(data.len());
+    let mut dropped = Vec::new();
+
+    for (index, data_point) in data.iter().enumerate() {
+        match knn.predict_excluding(&data_point.features, index) {
+            Ok(prediction) if prediction != data_point.label => dropped.push(index),
+            // a point without other points to vote keeps its label
+            _ => kept.push(*data_point),
+        }
+    }
+
+    Ok((kept, dropped))
+}
+
+#[cfg(test)]
+mod tests {
+    use kiddo::Manhattan;
+
+    use super::*;
+    use crate::parse::breast_cancer::{opposite_diagnosis, Diagnosis};
+
+    #[test]
+    fn flipped_labels_are_dropped() {
+        const FLIPPED: [usize; 3] = [7, 42, 77];
+
+        // two well separated 10 by 10 grids
+        let data: Vec<Data<Diagnosis, 2>> = (0..100)
+            .map(|index| {
+                let (center, label) = if index < 50 {
+                    (0.0, Diagnosis::Benign)
+                } else {
+                    (10.0, Diagnosis::Malignant)
+                };
+                let label = if FLIPPED.contains(&index) {
+                    opposite_diagnosis(label)
+                } else {
+                    label
+                };
+
+                Data {
+                    features: [
+                        center + (index % 10) as f64 * 0.1,
+                        center + (index / 10) as f64 * 0.1,
+                    ],
+                    label,
+                }
+            })
+            .collect();
+
+        let (kept, dropped) = edit_enn::<Manhattan, Diagnosis, 2>(&data, 5).unwrap();
+
+        for flipped in FLIPPED {
+            assert!(
+                dropped.contains(&flipped),
+                "kept {flipped}, dropped {dropped:?}"
+            );
+        }
+        assert_eq!(kept.len() + dropped.len(), data.len());
+    }
+}
@@ -0,0 +1,155 @@
+use std::cmp::Ordering;
+
+use kiddo::{
+    distance_metric::DistanceMetric,
+    float::kdtree::{Axis, KdTree},
+    NearestNeighbour,
+};
+
+/// Points per kd-tree leaf. kiddo takes the bucket size as a const, so only
+/// these sizes are supported. Smaller buckets mean shallower scans per leaf
+/// but a deeper tree, and a bucket must be larger than the number of points
+/// sharing one value on any axis, the model grows it when they don't fit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BucketSize {
+    B8,
+    B16,
+    #[default]
+    B32,
+    B64,
+}
+
+impl BucketSize {
+    #[must_use]
+    pub fn capacity(self) -> usize {
+        match self {
+            Self::B8 => 8,
+            Self::B16 => 16,
+            Self::B32 => 32,
+            Self::B64 => 64,
+        }
+    }
+
+    /// The smallest size from `self` up whose leaves can still be split with
+    /// `shared` points on one value of an axis, `None` when not even `B64` can
+    pub(crate) fn fitting(self, shared: usize) -> Option<Self> {
+        [Self::B8, Self::B16, Self::B32, Self::B64]
+            .into_iter()
+            .find(|&size| size >= self && shared < size.capacity())
+    }
+}
+
+/// The largest number of `points` sharing one value on any axis
+pub(crate) fn max_shared<'a, T: Axis + 'a, const D: usize>(
+    points: impl Iterator<Item = &'a [T; D]>,
+) -> usize {
+    let points: Vec<&[T; D]> = points.collect();
+
+    (0..D)
+        .map(|axis| {
+            let mut values: Vec<T> = points.iter().map(|point| point[axis]).collect();
+            values.sort_by(|first, second| first.partial_cmp(second).unwrap_or(Ordering::Equal));
+
+            values
+                .chunk_by(|first, second| first == second)
+                .map(<[T]>::len)
+                .max()
+                .unwrap_or(0)
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum Tree<T: Axis, const D: usize> {
+    B8(KdTree<T, usize, D, 8, u32>),
+    B16(KdTree<T, usize, D, 16, u32>),
+    B32(KdTree<T, usize, D, 32, u32>),
+    B64(KdTree<T, usize, D, 64, u32>),
+}
+
+/// Runs `$body` with `$tree` bound to the kd-tree of whatever bucket size
+macro_rules! with_tree {
+    ($self:expr, $tree:ident => $body:expr) => {
+        match $self {
+            Tree::B8($tree) => $body,
+            Tree::B16($tree) => $body,
+            Tree::B32($tree) => $body,
+            Tree::B64($tree) => $body,
+        }
+    };
+}
+
+impl<T: Axis, const D: usize> Tree<T, D> {
+    pub(crate) fn with_capacity(bucket_size: BucketSize, capacity: usize) -> Self {
+        match bucket_size {
+            BucketSize::B8 => Self::B8(KdTree::with_capacity(capacity)),
+            BucketSize::B16 => Self::B16(KdTree::with_capacity(capacity)),
+            BucketSize::B32 => Self::B32(KdTree::with_capacity(capacity)),
+            BucketSize::B64 => Self::B64(KdTree::with_capacity(capacity)),
+        }
+    }
+
+    pub(crate) fn bucket_size(&self) -> BucketSize {
+        match self {
+            Self::B8(_) => BucketSize::B8,
+            Self::B16(_) => BucketSize::B16,
+            Self::B32(_) => BucketSize::B32,
+            Self::B64(_) => BucketSize::B64,
+        }
+    }
+
+    pub(crate) fn add(&mut self, point: &[T; D], item: usize) {
+        with_tree!(self, tree => tree.add(point, item));
+    }
+
+    pub(crate) fn remove(&mut self, point: &[T; D], item: usize) {
+        with_tree!(self, tree => tree.remove(point, item));
+    }
+
+    pub(crate) fn within<Q: DistanceMetric<T, D>>(
+        &self,
+        query: &[T; D],
+        radius: T,
+    ) -> Vec<NearestNeighbour<T, usize>> {
+        with_tree!(self, tree => tree.within::<Q>(query, radius))
+    }
+
+    pub(crate) fn nearest_n<Q: DistanceMetric<T, D>>(
+        &self,
+        query: &[T; D],
+        count: usize,
+    ) -> Vec<NearestNeighbour<T, usize>> {
+        with_tree!(self, tree => tree.nearest_n::<Q>(query, count))
+    }
+
+    pub(crate) fn nearest_one<Q: DistanceMetric<T, D>>(
+        &self,
+        query: &[T; D],
+    ) -> NearestNeighbour<T, usize> {
+        with_tree!(self, tree => tree.nearest_one::<Q>(query))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_grow_past_the_points_sharing_a_value() {
+        assert_eq!(BucketSize::B8.fitting(7), Some(BucketSize::B8));
+        assert_eq!(BucketSize::B8.fitting(8), Some(BucketSize::B16));
+        assert_eq!(BucketSize::B8.fitting(20), Some(BucketSize::B32));
+        assert_eq!(BucketSize::B64.fitting(0), Some(BucketSize::B64));
+        assert_eq!(BucketSize::B8.fitting(64), None);
+    }
+
+    #[test]
+    fn shared_values_are_counted_per_axis() {
+        let points = [[0.0, 1.0], [0.0, 2.0], [1.0, 2.0], [2.0, 2.0], [0.0, 3.0]];
+
+        assert_eq!(max_shared(points.iter()), 3);
+        assert_eq!(max_shared::<f64, 2>(std::iter::empty()), 0);
+    }
+}
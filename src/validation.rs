@@ -0,0 +1,332 @@
+use std::sync::Arc;
+
+use kiddo::distance_metric::DistanceMetric;
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+use crate::{
+    distance_metric::MetricKind,
+    knn::{Data, Knn, KnnError, Label, Scalar},
+    search::Hyperparameters,
+};
+
+/// Contiguous `[start, end)` bounds of every fold, each holding out at least
+/// one of the `len` points
+fn fold_bounds(len: usize, k_folds: usize) -> Result<Vec<(usize, usize)>, KnnError> {
+    if k_folds == 0 || k_folds > len {
+        return Err(KnnError::InvalidInput(format!(
+            "need between 1 and {len} folds for {len} points, got {k_folds}"
+        )));
+    }
+
+    Ok((0..k_folds)
+        .map(|fold| (fold * len / k_folds, (fold + 1) * len / k_folds))
+        .collect())
+}
+
+fn fit_without_fold<M, L, const D: usize, T>(
+    data: &[Data<L, D, T>],
+    start: usize,
+    end: usize,
+    hyperparameters: &Hyperparameters,
+) -> Result<Knn<M, L, D, T>, KnnError>
+where
+    M: DistanceMetric<T, D> + MetricKind,
+    L: Label,
+    T: Scalar,
+{
+    let train_data: Vec<Data<L, D, T>> =
+        data[..start].iter().chain(&data[end..]).copied().collect();
+
+    let mut knn: Knn<M, L, D, T> = Knn::with_params(
+        hyperparameters.k,
+        hyperparameters.radius,
+        &hyperparameters.window,
+        Arc::clone(&hyperparameters.kernel),
+    )?;
+    knn.set_vote_weighting(hyperparameters.voting)?;
+    knn.fit(train_data, None, false)?;
+
+    Ok(knn)
+}
+
+/// Accuracy in percent on every held-out fold. Folds are contiguous unless
+/// `shuffle_seed` is given, in which case the data is shuffled first. Fails
+/// with [`KnnError::InvalidInput`] unless `1 <= k_folds <= data.len()`
+pub fn k_fold_cross_validate<M, L, const D: usize, T>(
+    data: &[Data<L, D, T>],
+    k_folds: usize,
+    hyperparameters: &Hyperparameters,
+    shuffle_seed: Option<u64>,
+) -> Result<Vec<f64>, KnnError>
+where
+    M: DistanceMetric<T, D> + MetricKind,
+    L: Label,
+    T: Scalar,
+{
+    let mut data = data.to_vec();
+    if let Some(seed) = shuffle_seed {
+        data.shuffle(&mut StdRng::seed_from_u64(seed));
+    }
+
+    fold_bounds(data.len(), k_folds)?
+        .into_iter()
+        .map(|(start, end)| {
+            let knn: Knn<M, L, D, T> = fit_without_fold(&data, start, end, hyperparameters)?;
+            let held_out = &data[start..end];
+
+            let correct_predictions = held_out
+                .iter()
+                .filter(|data_point| {
+                    knn.predict(&data_point.features)
+                        .is_ok_and(|prediction| prediction == data_point.label)
+                })
+                .count();
+
+            Ok((correct_predictions as f64 / held_out.len() as f64) * 100.0)
+        })
+        .collect()
+}
+
+/// Predicts every point with a model fitted on the other folds, `None` where
+/// that model found no neighbours. Fails like [`k_fold_cross_validate`]
+pub fn oof_predictions<M, L, const D: usize, T>(
+    data: &[Data<L, D, T>],
+    k_folds: usize,
+    hyperparameters: &Hyperparameters,
+) -> Result<Vec<Option<L>>, KnnError>
+where
+    M: DistanceMetric<T, D> + MetricKind,
+    L: Label,
+    T: Scalar,
+{
+    let mut predictions = Vec::with_capacity(data.len());
+
+    for (start, end) in fold_bounds(data.len(), k_folds)? {
+        let knn: Knn<M, L, D, T> = fit_without_fold(data, start, end, hyperparameters)?;
+
+        predictions.extend(
+            data[start..end]
+                .iter()
+                .map(|data_point| knn.predict(&data_point.features).ok()),
+        );
+    }
+
+    Ok(predictions)
+}
+
+#[cfg(test)]
+mod tests {
+    use kiddo::Manhattan;
+
+    use rand::Rng;
+
+    use super::*;
+    use crate::{
+        distance_metric::Chebyshev,
+        kernel::{Epanechnikov, Uniform},
+        knn::WindowType,
+        lowess::lowess,
+        metrics::{calculate_accuracy, prediction_agreement},
+        monitor::DriftDetector,
+        parse::breast_cancer::Diagnosis,
+    };
+
+    fn two_clusters() -> Vec<Data<Diagnosis, 2>> {
+        (0..20)
+            .map(|index| {
+                let offset = f64::from(index) * 0.1;
+                if index % 2 == 0 {
+                    Data {
+                        features: [offset, 0.0],
+                        label: Diagnosis::Benign,
+                    }
+                } else {
+                    Data {
+                        features: [10.0 + offset, 0.0],
+                        label: Diagnosis::Malignant,
+                    }
+                }
+            })
+            .collect()
+    }
+
+    fn hyperparameters() -> Hyperparameters {
+        Hyperparameters {
+            k: 3,
+            window: WindowType::Unfixed,
+            kernel: Arc::new(Uniform),
+            ..Hyperparameters::new()
+        }
+    }
+
+    #[test]
+    fn five_folds_give_five_percentages() {
+        let accuracies = k_fold_cross_validate::<Manhattan, _, 2, _>(
+            &two_clusters(),
+            5,
+            &hyperparameters(),
+            Some(7),
+        )
+        .unwrap();
+
+        assert_eq!(accuracies.len(), 5);
+        assert!(accuracies
+            .iter()
+            .all(|accuracy| (0.0..=100.0).contains(accuracy)));
+    }
+
+    #[test]
+    fn oof_predictions_cover_every_point() {
+        let data = two_clusters();
+        let predictions =
+            oof_predictions::<Manhattan, _, 2, _>(&data, 5, &hyperparameters()).unwrap();
+
+        assert_eq!(predictions.len(), data.len());
+        for (prediction, data_point) in predictions.iter().zip(&data) {
+            assert_eq!(*prediction, Some(data_point.label));
+        }
+    }
+
+    #[test]
+    fn folds_hold_out_every_point_exactly_once() {
+        for len in 1..12 {
+            for k_folds in 1..=len {
+                let bounds = fold_bounds(len, k_folds).unwrap();
+                let mut held_out = vec![0; len];
+                for &(start, end) in &bounds {
+                    assert!(start < end, "{len} points in {k_folds} folds");
+                    for count in &mut held_out[start..end] {
+                        *count += 1;
+                    }
+                }
+
+                assert_eq!(bounds.len(), k_folds);
+                assert!(held_out.iter().all(|&count| count == 1));
+            }
+        }
+    }
+
+    #[test]
+    fn leave_one_out_predicts_every_point_once() {
+        let data = two_clusters();
+        let predictions =
+            oof_predictions::<Manhattan, _, 2, _>(&data, data.len(), &hyperparameters()).unwrap();
+
+        assert_eq!(predictions.len(), data.len());
+        assert!(predictions.iter().all(Option::is_some));
+    }
+
+    #[test]
+    fn fold_counts_outside_the_data_are_rejected() {
+        let data = two_clusters();
+
+        for k_folds in [0, data.len() + 1] {
+            assert!(matches!(
+                oof_predictions::<Manhattan, _, 2, _>(&data, k_folds, &hyperparameters()),
+                Err(KnnError::InvalidInput(_))
+            ));
+            assert!(matches!(
+                k_fold_cross_validate::<Manhattan, _, 2, _>(
+                    &data,
+                    k_folds,
+                    &hyperparameters(),
+                    None
+                ),
+                Err(KnnError::InvalidInput(_))
+            ));
+        }
+        assert!(matches!(
+            oof_predictions::<Manhattan, Diagnosis, 2, f64>(&[], 1, &hyperparameters()),
+            Err(KnnError::InvalidInput(_))
+        ));
+    }
+
+    /// Overlapping classes on a grid of eighths, which `f32` holds exactly,
+    /// so both precisions measure identical distances
+    fn noisy_grid() -> Vec<Data<Diagnosis, 2>> {
+        let mut rng = StdRng::seed_from_u64(11);
+
+        (0..80)
+            .map(|_| {
+                let features = [
+                    f64::from(rng.gen_range(0..64)) / 8.0,
+                    f64::from(rng.gen_range(0..64)) / 8.0,
+                ];
+                let label = if features[0] + rng.gen_range(-2.0..2.0) > 4.0 {
+                    Diagnosis::Malignant
+                } else {
+                    Diagnosis::Benign
+                };
+
+                Data { features, label }
+            })
+            .collect()
+    }
+
+    #[test]
+    #[allow(clippy::cast_possible_truncation)]
+    fn f32_features_evaluate_like_f64() {
+        let data = noisy_grid();
+        let narrow: Vec<Data<Diagnosis, 2, f32>> = data
+            .iter()
+            .map(|data_point| Data {
+                features: data_point.features.map(|value| value as f32),
+                label: data_point.label,
+            })
+            .collect();
+        let hyperparameters = Hyperparameters {
+            k: 5,
+            window: WindowType::Unfixed,
+            kernel: Arc::new(Epanechnikov),
+            ..Hyperparameters::new()
+        };
+
+        assert_eq!(
+            k_fold_cross_validate::<Manhattan, _, 2, _>(&narrow, 4, &hyperparameters, Some(3))
+                .unwrap(),
+            k_fold_cross_validate::<Manhattan, _, 2, _>(&data, 4, &hyperparameters, Some(3))
+                .unwrap()
+        );
+        assert_eq!(
+            oof_predictions::<Manhattan, _, 2, _>(&narrow, 4, &hyperparameters).unwrap(),
+            oof_predictions::<Manhattan, _, 2, _>(&data, 4, &hyperparameters).unwrap()
+        );
+        assert_eq!(
+            lowess::<Manhattan, _, 2, _>(5, 0.0, WindowType::Unfixed, Epanechnikov, &narrow, 1)
+                .unwrap(),
+            lowess::<Manhattan, _, 2, _>(5, 0.0, WindowType::Unfixed, Epanechnikov, &data, 1)
+                .unwrap()
+        );
+
+        let (train, test) = data.split_at(60);
+        let (narrow_train, narrow_test) = narrow.split_at(60);
+        let wide: Knn<Manhattan, Diagnosis, 2> =
+            fit_without_fold(train, 0, 0, &hyperparameters).unwrap();
+        let narrow: Knn<Manhattan, Diagnosis, 2, f32> =
+            fit_without_fold(narrow_train, 0, 0, &hyperparameters).unwrap();
+
+        assert_eq!(
+            calculate_accuracy(&narrow, narrow_test).to_bits(),
+            calculate_accuracy(&wide, test).to_bits()
+        );
+        let wide_chebyshev: Knn<Chebyshev, Diagnosis, 2> =
+            fit_without_fold(train, 0, 0, &hyperparameters).unwrap();
+        let narrow_chebyshev: Knn<Chebyshev, Diagnosis, 2, f32> =
+            fit_without_fold(narrow_train, 0, 0, &hyperparameters).unwrap();
+        assert_eq!(
+            prediction_agreement(&narrow, &narrow_chebyshev, narrow_test).to_bits(),
+            prediction_agreement(&wide, &wide_chebyshev, test).to_bits()
+        );
+
+        let mut narrow_drift = DriftDetector::from_model(&narrow);
+        let mut wide_drift = DriftDetector::from_model(&wide);
+        for (narrow_point, wide_point) in narrow_test.iter().zip(test) {
+            narrow_drift.observe(&narrow, &narrow_point.features);
+            wide_drift.observe(&wide, &wide_point.features);
+        }
+        assert_eq!(
+            format!("{:?}", narrow_drift.report()),
+            format!("{:?}", wide_drift.report())
+        );
+    }
+}
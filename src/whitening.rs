@@ -0,0 +1,157 @@
+use nalgebra::{DMatrix, SymmetricEigen};
+
+/// Relative eigenvalue below which a principal direction counts as having no
+/// variance at all
+const VARIANCE_TOLERANCE: f64 = 1e-12;
+
+/// PCA whitening: centres points and rotates them onto their principal axes
+/// scaled to unit variance
+#[derive(Debug, Clone)]
+pub struct Whitening<const D: usize> {
+    mean: [f64; D],
+    transform: [[f64; D]; D],
+}
+
+impl<const D: usize> Whitening<D> {
+    /// `None` for fewer than two points. Directions without variance are
+    /// mapped to zero instead of being blown up
+    pub fn fit(points: &[[f64; D]]) -> Option<Self> {
+        if points.len() < 2 {
+            return None;
+        }
+
+        let mean = mean(points);
+        let covariance = DMatrix::from_row_slice(D, D, covariance(points).as_flattened());
+        let eigen = SymmetricEigen::new(covariance);
+
+        let max_eigenvalue = eigen.eigenvalues.iter().copied().fold(0.0, f64::max);
+
+        let mut transform = [[0.0; D]; D];
+        for (component, row) in transform.iter_mut().enumerate() {
+            let eigenvalue = eigen.eigenvalues[component];
+            if eigenvalue <= max_eigenvalue * VARIANCE_TOLERANCE {
+                continue;
+            }
+
+            let scale = eigenvalue.sqrt().recip();
+            for (axis, value) in row.iter_mut().enumerate() {
+                *value = eigen.eigenvectors[(axis, component)] * scale;
+            }
+        }
+
+        Some(Self { mean, transform })
+    }
+
+    pub fn apply(&self, x: &[f64; D]) -> [f64; D] {
+        let mut whitened = [0.0; D];
+
+        for (value, row) in whitened.iter_mut().zip(&self.transform) {
+            *value = row
+                .iter()
+                .zip(x.iter().zip(&self.mean))
+                .map(|(weight, (coordinate, mean))| weight * (coordinate - mean))
+                .sum();
+        }
+
+        whitened
+    }
+}
+
+fn mean<const D: usize>(points: &[[f64; D]]) -> [f64; D] {
+    let mut mean = [0.0; D];
+
+    for point in points {
+        for (sum, coordinate) in mean.iter_mut().zip(point) {
+            *sum += coordinate;
+        }
+    }
+    for sum in &mut mean {
+        *sum /= points.len() as f64;
+    }
+
+    mean
+}
+
+/// Sample covariance, so whitened training points have identity covariance
+pub fn covariance<const D: usize>(points: &[[f64; D]]) -> [[f64; D]; D] {
+    let mean = mean(points);
+    let mut covariance = [[0.0; D]; D];
+
+    for point in points {
+        for (row, first) in covariance.iter_mut().zip(point.iter().zip(&mean)) {
+            for (value, second) in row.iter_mut().zip(point.iter().zip(&mean)) {
+                *value += (first.0 - first.1) * (second.0 - second.1);
+            }
+        }
+    }
+    for value in covariance.as_flattened_mut() {
+        *value /= (points.len() - 1) as f64;
+    }
+
+    covariance
+}
+
+#[cfg(test)]
+mod tests {
+    use kiddo::SquaredEuclidean;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::{
+        kernel::Uniform,
+        knn::{Data, Knn, WindowType},
+        parse::breast_cancer::Diagnosis,
+    };
+
+    fn correlated_points() -> Vec<[f64; 3]> {
+        let mut rng = StdRng::seed_from_u64(5);
+
+        (0..200)
+            .map(|_| {
+                let (a_val, b_val, c_val): (f64, f64, f64) = (rng.gen(), rng.gen(), rng.gen());
+                [
+                    a_val,
+                    2.0 * a_val + 0.3 * b_val,
+                    b_val - a_val + 0.1 * c_val,
+                ]
+            })
+            .collect()
+    }
+
+    fn assert_identity(covariance: &[[f64; 3]; 3]) {
+        for (row_index, row) in covariance.iter().enumerate() {
+            for (column_index, value) in row.iter().enumerate() {
+                let expected = if row_index == column_index { 1.0 } else { 0.0 };
+                assert!((value - expected).abs() < 1e-9, "{covariance:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn whitened_points_have_identity_covariance() {
+        let points = correlated_points();
+        let whitening = Whitening::fit(&points).unwrap();
+
+        let whitened: Vec<[f64; 3]> = points.iter().map(|point| whitening.apply(point)).collect();
+
+        assert_identity(&covariance(&whitened));
+    }
+
+    #[test]
+    fn models_fitted_with_whitening_store_whitened_points() {
+        let data: Vec<Data<Diagnosis, 3>> = correlated_points()
+            .into_iter()
+            .map(|features| Data {
+                features,
+                label: Diagnosis::Benign,
+            })
+            .collect();
+        let mut knn: Knn<SquaredEuclidean, Diagnosis, 3> =
+            Knn::with_params(3, 0.0, &WindowType::Unfixed, Uniform).unwrap();
+
+        knn.with_pca_whitening(&data).unwrap();
+
+        let stored: Vec<[f64; 3]> = knn.iter().map(|data_point| data_point.features).collect();
+        assert_identity(&covariance(&stored));
+    }
+}
@@ -0,0 +1,171 @@
+use kiddo::{distance_metric::DistanceMetric, Manhattan, SquaredEuclidean};
+use knn::{
+    distance_metric::{Chebyshev, Cosine, MetricKind},
+    kernel::{epanechnikov, gaussian, triangular, tricube, truncated_gaussian, uniform},
+    knn::{Data, Knn, KnnBuilder, VoteWeighting, WindowType},
+    parse::breast_cancer::Diagnosis,
+};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use std::{env, panic};
+
+const DIMENSIONS: usize = 3;
+const DEFAULT_CASES: usize = 2000;
+const QUERIES_PER_CASE: usize = 8;
+const MAX_POINTS: usize = 12;
+
+const KERNELS: [fn(f64) -> f64; 6] = [
+    uniform,
+    triangular,
+    epanechnikov,
+    tricube,
+    gaussian,
+    truncated_gaussian,
+];
+
+#[derive(Debug)]
+struct Case {
+    window: WindowType,
+    k: usize,
+    radius: f64,
+    kernel: usize,
+    voting: VoteWeighting,
+    metric: &'static str,
+    data: Vec<Data<Diagnosis, DIMENSIONS>>,
+    weights: Option<Vec<f64>>,
+    queries: Vec<[f64; DIMENSIONS]>,
+}
+
+/// Mostly small coordinates on a coarse grid, so duplicates and exact
+/// matches are common, with the occasional zero or huge value
+fn coordinate(rng: &mut StdRng) -> f64 {
+    match rng.gen_range(0..10) {
+        0 => 0.0,
+        1 => 1e12 * rng.gen_range(-1.0..1.0),
+        2 => 1e-12 * rng.gen_range(-1.0..1.0),
+        _ => f64::from(rng.gen_range(-3..=3)) * 0.5,
+    }
+}
+
+fn point(rng: &mut StdRng) -> [f64; DIMENSIONS] {
+    std::array::from_fn(|_| coordinate(rng))
+}
+
+fn random_case(rng: &mut StdRng) -> Case {
+    let len = rng.gen_range(0..=MAX_POINTS);
+    let data: Vec<Data<Diagnosis, DIMENSIONS>> = (0..len)
+        .map(|_| Data {
+            features: point(rng),
+            label: *[Diagnosis::Benign, Diagnosis::Malignant]
+                .choose(rng)
+                .unwrap(),
+        })
+        .collect();
+
+    let weights = rng.gen_bool(0.5).then(|| {
+        (0..len)
+            .map(|_| match rng.gen_range(0..4) {
+                0 => 0.0,
+                _ => rng.gen_range(0.0..2.0),
+            })
+            .collect()
+    });
+
+    let radius = match rng.gen_range(0..4) {
+        0 => 1e-9,
+        _ => rng.gen_range(0.1..5.0),
+    };
+    let window = match rng.gen_range(0..4) {
+        0 => WindowType::Fixed,
+        1 => WindowType::Unfixed,
+        2 => WindowType::Capped {
+            radius,
+            max_k: rng.gen_range(1..=MAX_POINTS + 2),
+        },
+        _ => WindowType::Adaptive {
+            m: rng.gen_range(1..=MAX_POINTS + 2),
+        },
+    };
+    let voting = match rng.gen_range(0..3) {
+        0 => VoteWeighting::Kernel,
+        1 => VoteWeighting::InverseDistance { epsilon: 1e-9 },
+        _ => VoteWeighting::Uniform,
+    };
+
+    let queries = (0..QUERIES_PER_CASE)
+        .map(|_| match (rng.gen_range(0..3), data.choose(rng)) {
+            // exact matches hit zero distances
+            (0, Some(data_point)) => data_point.features,
+            _ => point(rng),
+        })
+        .collect();
+
+    Case {
+        window,
+        k: rng.gen_range(1..=MAX_POINTS + 2),
+        radius,
+        kernel: rng.gen_range(0..KERNELS.len()),
+        voting,
+        metric: ["manhattan", "squared euclidean", "chebyshev", "cosine"]
+            .choose(rng)
+            .unwrap(),
+        data,
+        weights,
+        queries,
+    }
+}
+
+/// Number of queries that produced an error, a panic unwinds out of here
+fn run<M: DistanceMetric<f64, DIMENSIONS> + MetricKind>(case: &Case) -> usize {
+    let builder = KnnBuilder::new()
+        .k(case.k)
+        .radius(case.radius)
+        .window(case.window)
+        .kernel(KERNELS[case.kernel])
+        .voting(case.voting);
+
+    let Ok(mut knn): Result<Knn<M, Diagnosis, DIMENSIONS>, _> = builder.build() else {
+        return case.queries.len();
+    };
+    if knn
+        .fit(case.data.clone(), case.weights.clone(), false)
+        .is_err()
+    {
+        return case.queries.len();
+    }
+
+    case.queries
+        .iter()
+        .filter(|query| knn.predict(query).is_err())
+        .count()
+}
+
+fn run_case(case: &Case) -> usize {
+    match case.metric {
+        "manhattan" => run::<Manhattan>(case),
+        "squared euclidean" => run::<SquaredEuclidean>(case),
+        "chebyshev" => run::<Chebyshev>(case),
+        _ => run::<Cosine>(case),
+    }
+}
+
+/// Fits random small datasets across windows, kernels, votings and metrics
+/// and checks that `predict` returns instead of panicking. `FUZZ_SEED` and
+/// `FUZZ_CASES` reproduce a run
+#[test]
+fn predict_never_panics() {
+    let seed: u64 = env::var("FUZZ_SEED").map_or(0, |seed| seed.parse().unwrap());
+    let cases: usize = env::var("FUZZ_CASES").map_or(DEFAULT_CASES, |cases| cases.parse().unwrap());
+
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    let mut errors = 0;
+    for index in 0..cases {
+        let case = random_case(&mut rng);
+
+        errors += panic::catch_unwind(|| run_case(&case))
+            .unwrap_or_else(|_| panic!("case {index} with FUZZ_SEED={seed} panicked: {case:#?}"));
+    }
+
+    // every case fits something, so not all queries may fail
+    assert!(errors < cases * QUERIES_PER_CASE);
+}
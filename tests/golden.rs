@@ -0,0 +1,124 @@
+use kiddo::Manhattan;
+use knn::{
+    kernel::epanechnikov,
+    knn::{Data, Knn, WindowType},
+    parse::breast_cancer::{parse, Diagnosis, DIMENSIONS},
+};
+use serde::{Deserialize, Serialize};
+use std::{env, error::Error, fs};
+
+const DATA_FILEPATH: &str = "data/breast-cancer.csv";
+const GOLDEN_FILEPATH: &str = "golden/breast_cancer.json";
+const TRAIN_RATIO: f64 = 0.6;
+const PROBABILITY_TOLERANCE: f64 = 1e-9;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct GoldenRow {
+    row: usize,
+    prediction: Option<String>,
+    malignant_probability: Option<f64>,
+}
+
+fn breast_cancer_data() -> Result<Vec<Data<Diagnosis, DIMENSIONS>>, Box<dyn Error>> {
+    parse(DATA_FILEPATH)?
+        .into_iter()
+        .map(|entry| {
+            Ok(Data {
+                features: entry.values.try_into().map_err(|_| "unexpected width")?,
+                label: entry.diagnosis,
+            })
+        })
+        .collect()
+}
+
+fn golden_rows(data: &[Data<Diagnosis, DIMENSIONS>]) -> Result<Vec<GoldenRow>, Box<dyn Error>> {
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_sign_loss)]
+    let train_size = (data.len() as f64 * TRAIN_RATIO) as usize;
+    let (train_data, test_data) = data.split_at(train_size);
+
+    let mut knn: Knn<Manhattan, Diagnosis, DIMENSIONS> =
+        Knn::with_params(5, 1.0, &WindowType::Unfixed, epanechnikov)?;
+    knn.fit(train_data.to_vec(), None, false)?;
+
+    Ok(test_data
+        .iter()
+        .enumerate()
+        .map(|(index, data_point)| {
+            let probabilities = knn.predict_proba(&data_point.features).ok();
+
+            GoldenRow {
+                row: train_size + index,
+                prediction: knn
+                    .predict(&data_point.features)
+                    .ok()
+                    .map(|prediction| format!("{prediction:?}")),
+                malignant_probability: probabilities.map(|probabilities| {
+                    probabilities
+                        .get(&Diagnosis::Malignant)
+                        .copied()
+                        .unwrap_or(0.0)
+                }),
+            }
+        })
+        .collect())
+}
+
+fn changed(old: &GoldenRow, new: &GoldenRow) -> bool {
+    match (old.malignant_probability, new.malignant_probability) {
+        (Some(old_probability), Some(new_probability)) => {
+            old.prediction != new.prediction
+                || (old_probability - new_probability).abs() > PROBABILITY_TOLERANCE
+        }
+        (old_probability, new_probability) => {
+            old.prediction != new.prediction || old_probability != new_probability
+        }
+    }
+}
+
+/// Compares canonical breast-cancer predictions against the checked-in
+/// golden file, `UPDATE_GOLDEN=1 cargo test --test golden` rewrites it
+/// instead
+#[test]
+fn predictions_match_golden_file() -> Result<(), Box<dyn Error>> {
+    let rows = golden_rows(&breast_cancer_data()?)?;
+
+    if env::var("UPDATE_GOLDEN").is_ok_and(|value| value == "1") {
+        fs::write(GOLDEN_FILEPATH, serde_json::to_string_pretty(&rows)? + "\n")?;
+        println!("golden file updated: {GOLDEN_FILEPATH}");
+        return Ok(());
+    }
+
+    let golden: Vec<GoldenRow> = serde_json::from_str(&fs::read_to_string(GOLDEN_FILEPATH)?)?;
+
+    if golden.len() != rows.len() {
+        return Err(format!("expected {} rows, got {}", golden.len(), rows.len()).into());
+    }
+
+    let mut changes = 0;
+    for (old, new) in golden.iter().zip(&rows) {
+        if changed(old, new) {
+            changes += 1;
+
+            let delta = match (old.malignant_probability, new.malignant_probability) {
+                (Some(old_probability), Some(new_probability)) => {
+                    format!("{:+.6}", new_probability - old_probability)
+                }
+                _ => "n/a".to_string(),
+            };
+            println!(
+                "row {}: {:?} -> {:?}, probability delta {delta}",
+                new.row, old.prediction, new.prediction
+            );
+        }
+    }
+
+    if changes > 0 {
+        return Err(format!(
+            "{changes} predictions differ from {GOLDEN_FILEPATH}, rerun with UPDATE_GOLDEN=1 if intended"
+        )
+        .into());
+    }
+
+    Ok(())
+}